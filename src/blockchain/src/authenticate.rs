@@ -1,7 +1,7 @@
 //! Signature verification using Dilithium2 public-key cryptography.
 
 use crate::states::SharedData;
-use fleetcore::{BaseJournal, CommunicationData, SignedMessage};
+use fleetcore::{signer_digest, ChainError, CommunicationData, JournalHeader, SignedMessage};
 use pqcrypto_dilithium::dilithium2::{verify_detached_signature, DetachedSignature, PublicKey};
 use pqcrypto_traits::sign::{DetachedSignature as _, PublicKey as _};
 use serde::{Deserialize, Serialize};
@@ -14,33 +14,53 @@ use serde::{Deserialize, Serialize};
 ///
 /// # Returns
 /// - `Ok(())` if valid
-/// - `Err(msg)` if invalid
+/// - `Err(ChainError)` describing why the message was rejected
 pub fn authenticate(
     shared: &SharedData,
     signed: &SignedMessage<CommunicationData>,
-) -> Result<(), String> {
+) -> Result<(), ChainError> {
     let payload = &signed.payload;
     let signature = &signed.signature;
     let public_key = &signed.public_key;
 
     let message_bytes =
-        serde_json::to_vec(payload).map_err(|_| "Failed to serialize payload".to_string())?;
+        serde_json::to_vec(&(signed.nonce, payload)).map_err(|_| ChainError::MalformedPayload)?;
 
     if !verify_signature(&message_bytes, signature, public_key) {
-        return Err("Invalid signature".to_string());
+        return Err(ChainError::InvalidSignature);
     }
 
-    let journal: BaseJournal = payload
+    let journal: JournalHeader = payload
         .receipt
         .journal
         .decode()
-        .expect("Invalid journal data");
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // The proof must have been generated for the key that signed it
+    if journal.signer != signer_digest(public_key) {
+        return Err(ChainError::SignerMismatch);
+    }
 
     let gmap = shared.gmap.lock().unwrap();
     if let Some(game) = gmap.get(&journal.gameid) {
+        // Every proof in a game must use the scheme the game was created with
+        if journal.commitment_version != game.commitment_version {
+            return Err(ChainError::CommitmentVersionMismatch {
+                expected: game.commitment_version,
+                got: journal.commitment_version,
+            });
+        }
+
+        // ...and the rules it was created with
+        if journal.rules != game.config.rules.digest() {
+            return Err(ChainError::RulesMismatch);
+        }
+
         if let Some(player) = game.pmap.get(&journal.fleet) {
             if player.public_key != *public_key {
-                return Err(format!("Public key mismatch for player {}", journal.fleet));
+                return Err(ChainError::PublicKeyMismatch {
+                    fleet: journal.fleet,
+                });
             }
         }
     }
@@ -0,0 +1,207 @@
+//! The chain's smart contract, as a pluggable set of game rules.
+//!
+//! A [`GameContract`] decides which commands a game accepts in each phase and
+//! how each one changes the game. Every method defaults to the Battleship rules
+//! of the `handlers` module, so a variant only overrides what it changes.
+//! Contracts are registered by name, and a game is bound for its whole life to
+//! the one its creator named in [`GameConfig::contract`](fleetcore::GameConfig).
+
+use crate::handlers::{
+    handle_contest, handle_defeat, handle_fire, handle_history, handle_join, handle_report,
+    handle_reveal, handle_start, handle_wave, handle_win,
+};
+use crate::states::{Game, GamePhase, SharedData};
+use fleetcore::{ChainError, Command, CommunicationData, JournalHeader, DEFAULT_CONTRACT};
+use std::{collections::HashMap, sync::Arc};
+
+pub trait GameContract: Send + Sync {
+    /// Name a game creator picks the contract by.
+    fn name(&self) -> &'static str;
+
+    /// Phase a game enters once `cmd` is applied in `phase`, or why it cannot be.
+    fn transition(&self, phase: GamePhase, cmd: Command) -> Result<GamePhase, ChainError> {
+        phase.transition(cmd)
+    }
+
+    fn join(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_join(self, shared, input, public_key)
+    }
+
+    fn start(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_start(self, shared, input, public_key)
+    }
+
+    fn fire(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_fire(self, shared, input, public_key)
+    }
+
+    fn report(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_report(self, shared, input, public_key)
+    }
+
+    fn wave(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_wave(self, shared, input, public_key)
+    }
+
+    fn win(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_win(self, shared, input, public_key)
+    }
+
+    fn contest(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_contest(self, shared, input, public_key)
+    }
+
+    fn defeat(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_defeat(self, shared, input, public_key)
+    }
+
+    fn history(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_history(self, shared, input, public_key)
+    }
+
+    fn reveal(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        handle_reveal(self, shared, input, public_key)
+    }
+
+    /// Applies an authenticated transaction to its game.
+    fn apply(
+        &self,
+        shared: &SharedData,
+        input: &CommunicationData,
+        public_key: &[u8],
+    ) -> Result<(), ChainError> {
+        match input.cmd {
+            Command::Join => self.join(shared, input, public_key),
+            Command::Start => self.start(shared, input, public_key),
+            Command::Fire => self.fire(shared, input, public_key),
+            Command::Report => self.report(shared, input, public_key),
+            Command::Wave => self.wave(shared, input, public_key),
+            Command::Win => self.win(shared, input, public_key),
+            Command::Contest => self.contest(shared, input, public_key),
+            Command::Defeat => self.defeat(shared, input, public_key),
+            Command::History => self.history(shared, input, public_key),
+            Command::Reveal => self.reveal(shared, input, public_key),
+            Command::Timeout | Command::Close => {
+                Err(ChainError::ReservedCommand { cmd: input.cmd })
+            }
+        }
+    }
+}
+
+/// The classic rules, as implemented by the `handlers` module.
+pub struct Battleship;
+
+impl GameContract for Battleship {
+    fn name(&self) -> &'static str {
+        DEFAULT_CONTRACT
+    }
+}
+
+/// Contracts games can be created with, by name.
+pub struct ContractRegistry {
+    contracts: HashMap<&'static str, Arc<dyn GameContract>>,
+}
+
+impl ContractRegistry {
+    /// Makes a contract available to new games, replacing any of the same name.
+    pub fn register(&mut self, contract: Arc<dyn GameContract>) {
+        self.contracts.insert(contract.name(), contract);
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<dyn GameContract>, ChainError> {
+        self.contracts
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ChainError::UnknownContract {
+                name: name.to_string(),
+            })
+    }
+}
+
+impl Default for ContractRegistry {
+    /// A registry holding the Battleship rules only.
+    fn default() -> Self {
+        let mut registry = ContractRegistry {
+            contracts: HashMap::new(),
+        };
+        registry.register(Arc::new(Battleship));
+        registry
+    }
+}
+
+/// Contract a transaction is governed by: its game's, or for the Join that
+/// creates a game, the one named in the submitted configuration.
+pub fn contract_for(
+    shared: &SharedData,
+    input: &CommunicationData,
+) -> Result<Arc<dyn GameContract>, ChainError> {
+    let header: JournalHeader = input
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    let name = match shared.gmap.lock().unwrap().get(&header.gameid) {
+        Some(game) => game.config.contract.clone(),
+        None => input
+            .game_config
+            .as_ref()
+            .map_or_else(|| DEFAULT_CONTRACT.to_string(), |c| c.contract.clone()),
+    };
+    shared.contracts.get(&name)
+}
+
+/// Contract a game is bound to.
+pub fn contract_of(shared: &SharedData, game: &Game) -> Result<Arc<dyn GameContract>, ChainError> {
+    shared.contracts.get(&game.config.contract)
+}
@@ -0,0 +1,164 @@
+//! Fan-out of chain activity to clients.
+//!
+//! Publishing never fails, whether or not anyone is listening: a transaction is
+//! applied and recorded before it is announced, so delivery is best effort.
+//! Transactions are applied against a staging bus that holds their
+//! announcements back until the store has recorded them.
+//! Typed game events are numbered and the most recent ones of every game are
+//! kept in memory, so a client that connects late or drops its connection can
+//! catch up from the last event id it saw.
+
+use fleetcore::GameEvent;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+use tokio::sync::broadcast;
+
+/// Messages a slow subscriber may fall behind by before it starts skipping.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// A game event as delivered to clients: `{"id": 7, "type": "ShotFired", ...}`.
+#[derive(Clone, Debug, Serialize)]
+pub struct EventRecord {
+    pub id: u64, // Increases across all games and restarts, starting at 1
+    #[serde(skip)]
+    pub gameid: String,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+struct Backlog {
+    last_id: u64,                                  // Id of the last published event
+    games: HashMap<String, VecDeque<EventRecord>>, // Most recent events per game
+}
+
+/// An announcement a staging bus holds back instead of publishing.
+pub enum Held {
+    Log(String),
+    Event { gameid: String, event: GameEvent },
+}
+
+pub struct EventBus {
+    logs: broadcast::Sender<String>,        // HTML log lines for `/logs`
+    events: broadcast::Sender<EventRecord>, // Typed events for `/games/{id}/events`
+    backlog: Mutex<Backlog>,
+    backlog_size: usize,            // Events kept per game
+    held: Option<Mutex<Vec<Held>>>, // Set on a staging bus
+}
+
+impl EventBus {
+    /// Creates a bus numbering events from `last_id + 1`, the id after the last
+    /// one the store recorded, so ids keep increasing across restarts.
+    pub fn new(backlog_size: usize, last_id: u64) -> Self {
+        EventBus {
+            logs: broadcast::channel(CHANNEL_CAPACITY).0,
+            events: broadcast::channel(CHANNEL_CAPACITY).0,
+            backlog: Mutex::new(Backlog {
+                last_id,
+                games: HashMap::new(),
+            }),
+            backlog_size,
+            held: None,
+        }
+    }
+
+    /// Creates a bus that holds every log line and event back, in order, until
+    /// they are taken and published on a live bus.
+    pub fn staging() -> Self {
+        EventBus {
+            held: Some(Mutex::new(Vec::new())),
+            ..EventBus::new(0, 0)
+        }
+    }
+
+    /// Takes the announcements held back so far, oldest first.
+    pub fn take_held(&self) -> Vec<Held> {
+        self.held
+            .as_ref()
+            .map(|held| std::mem::take(&mut *held.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    /// Publishes announcements held back by a staging bus.
+    pub fn publish(&self, held: Vec<Held>) {
+        for announcement in held {
+            match announcement {
+                Held::Log(msg) => self.log(msg),
+                Held::Event { gameid, event } => self.emit(&gameid, event),
+            }
+        }
+    }
+
+    /// Id of the last published event.
+    pub fn last_id(&self) -> u64 {
+        self.backlog.lock().unwrap().last_id
+    }
+
+    /// Sends a log line to the `/logs` stream, turning newlines into `<br>`.
+    pub fn log(&self, msg: impl AsRef<str>) {
+        if let Some(held) = &self.held {
+            held.lock()
+                .unwrap()
+                .push(Held::Log(msg.as_ref().to_string()));
+            return;
+        }
+        // Nobody may be listening
+        let _ = self.logs.send(msg.as_ref().replace('\n', "<br>"));
+    }
+
+    pub fn subscribe_logs(&self) -> broadcast::Receiver<String> {
+        self.logs.subscribe()
+    }
+
+    /// Numbers a game event, keeps it in the game's backlog and publishes it.
+    pub fn emit(&self, gameid: &str, event: GameEvent) {
+        if let Some(held) = &self.held {
+            held.lock().unwrap().push(Held::Event {
+                gameid: gameid.to_string(),
+                event,
+            });
+            return;
+        }
+
+        let mut backlog = self.backlog.lock().unwrap();
+        backlog.last_id += 1;
+        let record = EventRecord {
+            id: backlog.last_id,
+            gameid: gameid.to_string(),
+            event,
+        };
+
+        let queue = backlog.games.entry(gameid.to_string()).or_default();
+        queue.push_back(record.clone());
+        if queue.len() > self.backlog_size {
+            queue.pop_front();
+        }
+
+        // Published under the lock so subscribers see events in id order
+        let _ = self.events.send(record);
+    }
+
+    /// Subscribes to future events and returns the backlogged events of `gameid`
+    /// published after event `since` (all of them if `None`).
+    ///
+    /// Both are taken under the same lock, so no event falls between the backlog
+    /// and the receiver. Receivers get the events of every game and must filter
+    /// on [`EventRecord::gameid`].
+    pub fn subscribe(
+        &self,
+        gameid: &str,
+        since: Option<u64>,
+    ) -> (Vec<EventRecord>, broadcast::Receiver<EventRecord>) {
+        let backlog = self.backlog.lock().unwrap();
+        let since = since.unwrap_or(0);
+        let missed = backlog
+            .games
+            .get(gameid)
+            .map(|queue| queue.iter().filter(|r| r.id > since).cloned().collect())
+            .unwrap_or_default();
+
+        (missed, self.events.subscribe())
+    }
+}
@@ -1,47 +1,84 @@
-use crate::{Game, SharedData};
-use fleetcore::{BaseJournal, CommunicationData, EncryptedToken};
+use crate::contract::GameContract;
+use crate::SharedData;
+use fleetcore::{BaseJournal, ChainError, Command, CommunicationData, FleetStatus, GameEvent};
 use methods::CONTEST_ID;
+use std::time::SystemTime;
 
-pub fn handle_contest(
+pub fn handle_contest<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
+) -> Result<(), ChainError> {
     if input_data.receipt.verify(CONTEST_ID).is_err() {
         shared
-            .tx
-            .send("Attempting to contest a win with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
+            .bus
+            .log("Attempting to contest a win with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::Contest,
+        });
     }
 
     // Decode journal
-    let data: BaseJournal = input_data.receipt.journal.decode().unwrap();
+    let data: BaseJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
 
     // Confirm game exists
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = match gmap.get_mut(&data.gameid) {
-        Some(g) => g,
-        None => return format!("Game {} not found\n", data.gameid),
-    };
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
 
-    // Confirm firing player exists and is valid
-    let player = match game.pmap.get(&data.fleet) {
-        Some(p) => p,
-        None => return format!("Player {} not found\n", data.fleet),
-    };
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Contest)?;
+
+    // Confirm contesting player exists and is valid
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
 
     if player.public_key != public_key {
-        return format!("Public key mismatch for player {}", data.fleet);
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
     }
 
     if data.board != player.current_state {
-        return "Fleet commitment does not match recorded state\n".to_string();
+        return Err(ChainError::CommitmentMismatch);
+    }
+
+    // Defeated and forfeited fleets are out of the game
+    if !player.is_active() {
+        return Err(ChainError::InactiveChallenger { fleet: data.fleet });
     }
 
     if let Some(pending) = &game.pending_win {
         if pending.claimant == data.fleet {
-            return "You cannot contest your own victory\n".to_string();
+            return Err(ChainError::OwnClaim);
+        }
+
+        // The claim is settled once the window has passed, swept or not
+        let open = SystemTime::now()
+            .duration_since(pending.time)
+            .is_ok_and(|age| age < shared.config.contest_window);
+        if !open {
+            return Err(ChainError::ContestWindowClosed);
+        }
+
+        // The challenger must still be afloat in the standings the claim was proven with
+        let afloat = pending
+            .standings
+            .iter()
+            .any(|s| s.fleet == data.fleet && s.status == FleetStatus::Afloat);
+        if !afloat {
+            return Err(ChainError::InactiveChallenger { fleet: data.fleet });
         }
 
         // Contest is valid
@@ -49,17 +86,30 @@ pub fn handle_contest(
         let gid = data.gameid.clone();
         let challenger = data.fleet.clone();
 
-        // Not pending anymore
+        // Not pending anymore; the turn clock restarts where play resumes
         game.pending_win = None;
+        game.phase = next_phase;
+        game.turn_deadline = Some(SystemTime::now() + shared.config.turn_timeout);
+
+        shared.bus.emit(
+            &gid,
+            GameEvent::WinContested {
+                claimant: claimant.clone(),
+                challenger: challenger.clone(),
+            },
+        );
 
         let msg = format!(
             "Victory claim by {} has been successfully contested by {} in game {}!\n\n\n",
             claimant, challenger, gid
         );
-        shared.tx.send(msg.replace('\n', "<br>")).unwrap();
+        shared.bus.log(msg);
 
-        return "OK".to_string();
+        return Ok(());
     }
 
-    "No active victory claim to contest.".to_string()
+    Err(ChainError::IllegalCommand {
+        cmd: Command::Contest,
+        phase: game.phase.to_string(),
+    })
 }
@@ -0,0 +1,107 @@
+use crate::contract::GameContract;
+use crate::tokens::issue_turn_token;
+use crate::SharedData;
+use fleetcore::{BaseJournal, ChainError, Command, CommunicationData, GameEvent};
+use methods::DEFEAT_ID;
+
+pub fn handle_defeat<C: GameContract + ?Sized>(
+    contract: &C,
+    shared: &SharedData,
+    input_data: &CommunicationData,
+    public_key: &[u8],
+) -> Result<(), ChainError> {
+    if input_data.receipt.verify(DEFEAT_ID).is_err() {
+        shared
+            .bus
+            .log("Attempting to concede defeat with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::Defeat,
+        });
+    }
+
+    // Decode journal
+    let data: BaseJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // Confirm game exists
+    let mut gmap = shared.gmap.lock().unwrap();
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Defeat)?;
+
+    // Confirm defeated player exists and is valid
+    let player = game
+        .pmap
+        .get_mut(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Confirm public key matches
+    if player.public_key != public_key {
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
+    }
+
+    // The proof shows that the committed board is empty
+    if data.board != player.current_state {
+        return Err(ChainError::CommitmentMismatch);
+    }
+
+    if player.eliminated {
+        return Err(ChainError::PlayerEliminated { fleet: data.fleet });
+    }
+
+    // Take the fleet out of the rotation
+    player.eliminated = true;
+    game.phase = next_phase;
+
+    let mut msg = format!(
+        "\
+        \x20 Fleet {} has been sunk!\n\
+        \x20 ▶ {} is out of game {}.\n",
+        data.fleet, data.fleet, data.gameid
+    );
+
+    // A defeated fleet cannot keep the turn; hand it to the next one
+    let mut passed_to = None;
+    if game.turn_holder.as_deref() == Some(data.fleet.as_str()) {
+        if let Some(next) = game.next_player_after(&data.fleet) {
+            match issue_turn_token(shared, &game.pmap[&next].rsa_pubkey) {
+                Ok(token) => {
+                    game.pass_turn(&token, shared.config.turn_timeout);
+                    msg += &format!("\x20 ▶ Turn passed to {}.\n", next);
+                    passed_to = Some(next);
+                }
+                // The turn timeout will move it along instead
+                Err(e) => eprintln!(
+                    "Failed to issue a turn token in game {}: {}",
+                    data.gameid, e
+                ),
+            }
+        }
+    }
+
+    shared.bus.emit(
+        &data.gameid,
+        GameEvent::PlayerDefeated {
+            fleet: data.fleet.clone(),
+        },
+    );
+    if let Some(next) = passed_to {
+        shared
+            .bus
+            .emit(&data.gameid, GameEvent::TurnPassed { to: next });
+    }
+    shared.bus.log(msg + "\n\n");
+
+    Ok(())
+}
@@ -1,92 +1,181 @@
-use crate::{xy_pos, Game, Player, SharedData};
-use fleetcore::{CommunicationData, EncryptedToken, FireJournal};
-use methods::FIRE_ID;
-
-use std::{
-    collections::HashMap,
-    error::Error,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
+use crate::contract::GameContract;
+use crate::states::PendingShot;
+use crate::{xy_pos, SharedData};
+use fleetcore::{
+    ChainError, Command, CommunicationData, EncryptedToken, FireJournal, GameEvent, GameMode,
+    SalvoJournal,
 };
+use methods::{FIRE_ID, SALVO_ID};
 
-pub fn handle_fire(
+pub fn handle_fire<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
-    if input_data.receipt.verify(FIRE_ID).is_err() {
-        shared
-            .tx
-            .send("Attempting to fire with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
-    }
-
-    // Decode journal
-    let data: FireJournal = input_data.receipt.journal.decode().unwrap();
+) -> Result<(), ChainError> {
+    // Classic games prove a single shot, salvo games a batch of them.
+    // Both are handled as a salvo from here on.
+    let (mode, data) = if input_data.receipt.verify(FIRE_ID).is_ok() {
+        let fire: FireJournal = input_data
+            .receipt
+            .journal
+            .decode()
+            .map_err(|_| ChainError::MalformedPayload)?;
+        let salvo = SalvoJournal {
+            gameid: fire.gameid,
+            fleet: fire.fleet,
+            signer: fire.signer,
+            commitment_version: fire.commitment_version,
+            rules: fire.rules,
+            board: fire.board,
+            target: fire.target,
+            shots: vec![fire.pos],
+            token_commitment: fire.token_commitment,
+            shot_log: fire.shot_log,
+            next_shot_log: fire.next_shot_log,
+            shots_taken: fire.shots_taken,
+        };
+        (GameMode::Classic, salvo)
+    } else if input_data.receipt.verify(SALVO_ID).is_ok() {
+        let salvo: SalvoJournal = input_data
+            .receipt
+            .journal
+            .decode()
+            .map_err(|_| ChainError::MalformedPayload)?;
+        (GameMode::Salvo, salvo)
+    } else {
+        shared.bus.log("Attempting to fire with invalid receipt");
+        return Err(ChainError::InvalidReceipt { cmd: Command::Fire });
+    };
 
     // Confirm game exists
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = match gmap.get_mut(&data.gameid) {
-        Some(g) => g,
-        None => return format!("Game {} not found\n", data.gameid),
-    };
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
 
-    if game.turn_commitment != Some(data.token_commitment) {
-        return "Invalid token: not your turn.\n".to_string();
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Fire)?;
+
+    // The proof must fit the game mode
+    if mode != game.config.mode {
+        return Err(ChainError::ModeMismatch {
+            mode: game.config.mode,
+        });
     }
 
-    // Verify if the player has reported before firing
-    if game.shot_position.is_some() {
-        return "You must report the last shot before firing.\n".to_string();
+    if game.turn_commitment != Some(data.token_commitment) {
+        return Err(ChainError::NotYourTurn);
     }
 
     // Confirm firing player exists and is valid
-    let player = match game.pmap.get(&data.fleet) {
-        Some(p) => p,
-        None => return format!("Player {} not found\n", data.fleet),
-    };
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
 
     // Confirm public key matches
     if player.public_key != public_key {
-        return format!("Public key mismatch for player {}", data.fleet);
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
     }
 
     // Validate commitment hash
     if data.board != player.current_state {
-        return "Fleet commitment does not match recorded state\n\n\n\
-         \x20"
-            .to_string();
+        return Err(ChainError::CommitmentMismatch);
+    }
+
+    // The proof must extend the shot log the chain recorded for this fleet
+    if data.shot_log != player.shot_log
+        || data.shots_taken != player.shots_taken + data.shots.len() as u32
+    {
+        return Err(ChainError::ShotLogMismatch);
+    }
+
+    // Every shot must land on the board
+    if let Some(&pos) = data
+        .shots
+        .iter()
+        .find(|&&pos| !game.config.rules.contains(pos))
+    {
+        return Err(ChainError::PositionOutOfBounds { pos });
     }
 
     // Validate target's existence
-    if !game.pmap.contains_key(&data.target) {
-        return format!(
-            "Target {} does not exist\n\n\n\
-        \x20",
-            data.target
-        );
+    if data.target == data.fleet {
+        return Err(ChainError::SelfTarget);
     }
+    let target_key = match game.pmap.get(&data.target) {
+        None => return Err(ChainError::TargetNotFound { fleet: data.target }),
+        Some(target) if target.forfeited => {
+            return Err(ChainError::TargetForfeited { fleet: data.target })
+        }
+        Some(target) if target.eliminated => {
+            return Err(ChainError::TargetEliminated { fleet: data.target })
+        }
+        Some(target) => target.rsa_pubkey.clone(),
+    };
+
+    // The target must answer the shot, so the token goes to it
+    let token_data: &EncryptedToken = match input_data.token_data.as_ref() {
+        Some(t) if t.pub_rsa_key == target_key => t,
+        Some(_) => return Err(ChainError::InvalidTokenRecipient),
+        None => return Err(ChainError::MissingToken),
+    };
 
     // Update game state with new token
-    let token_data: &EncryptedToken = input_data.token_data.as_ref().unwrap();
-    game.encrypted_token = Some(token_data.enc_token.clone());
-    game.turn_commitment = Some(token_data.token_hash.clone());
-    game.shot_position = Some(data.pos);
+    if let Some(player) = game.pmap.get_mut(&data.fleet) {
+        player.shot_log = data.next_shot_log;
+        player.shots_taken = data.shots_taken;
+    }
+    game.pass_turn(token_data, shared.config.turn_timeout);
+    game.pending_shot = Some(PendingShot {
+        shooter: data.fleet.clone(),
+        target: data.target.clone(),
+        shots: data.shots.clone(),
+    });
+    game.phase = next_phase;
 
+    let positions: Vec<String> = data
+        .shots
+        .iter()
+        .map(|&pos| xy_pos(Some(pos), &game.config.rules))
+        .collect();
     let msg = format!(
         "\
         \x20 Shots fired!\n\
         \x20 ▶ {} fired at position {} targeting {} in game {}\n\n\n\
         \x20",
         data.fleet,
-        xy_pos(Some(data.pos)),
+        positions.join(", "),
         data.target,
         data.gameid
     );
 
-    let html_msg = msg.replace('\n', "<br>");
-    shared.tx.send(html_msg.clone()).unwrap();
+    let event = match mode {
+        GameMode::Classic => GameEvent::ShotFired {
+            shooter: data.fleet.clone(),
+            target: data.target.clone(),
+            pos: data.shots[0],
+        },
+        GameMode::Salvo => GameEvent::SalvoFired {
+            shooter: data.fleet.clone(),
+            target: data.target.clone(),
+            shots: data.shots.clone(),
+        },
+    };
+    shared.bus.emit(&data.gameid, event);
+    if let Some(holder) = &game.turn_holder {
+        shared
+            .bus
+            .emit(&data.gameid, GameEvent::TurnPassed { to: holder.clone() });
+    }
+
+    shared.bus.log(msg);
 
-    "OK".to_string()
+    Ok(())
 }
@@ -0,0 +1,103 @@
+use crate::contract::GameContract;
+use crate::SharedData;
+use fleetcore::{ChainError, Command, CommunicationData, HistoryImageIds, HistoryJournal};
+use methods::{HISTORY_ID, JOIN_ID, REPORT_ID, SALVO_REPORT_ID};
+use risc0_zkvm::Digest;
+
+/// Program images a history proof must have been built from.
+pub fn trusted_images() -> HistoryImageIds {
+    HistoryImageIds {
+        history: Digest::from(HISTORY_ID),
+        join: Digest::from(JOIN_ID),
+        report: Digest::from(REPORT_ID),
+        salvo_report: Digest::from(SALVO_REPORT_ID),
+    }
+}
+
+/// Accepts a proof that a fleet's current board descends from the one it joined
+/// with. The proof itself stays in the ledger, where spectators can fetch it.
+pub fn handle_history<C: GameContract + ?Sized>(
+    contract: &C,
+    shared: &SharedData,
+    input_data: &CommunicationData,
+    public_key: &[u8],
+) -> Result<(), ChainError> {
+    if input_data.receipt.verify(HISTORY_ID).is_err() {
+        shared
+            .bus
+            .log("Attempting to prove a history with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::History,
+        });
+    }
+
+    // Decode journal
+    let data: HistoryJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // Every folded receipt must come from this chain's programs
+    if data.image_ids != trusted_images() {
+        return Err(ChainError::UntrustedImage);
+    }
+
+    // Reports the fleet has on the ledger. Counted before the game is locked,
+    // as sealing a block locks the ledger first.
+    let recorded = shared
+        .ledger
+        .lock()
+        .unwrap()
+        .history(&data.gameid)
+        .filter(|(_, tx)| tx.fleet == data.fleet && tx.cmd == Command::Report)
+        .count() as u32;
+
+    // Confirm game exists
+    let gmap = shared.gmap.lock().unwrap();
+    let game = gmap
+        .get(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    contract.transition(game.phase, Command::History)?;
+
+    // Confirm player exists and is valid
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Confirm public key matches
+    if player.public_key != public_key {
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
+    }
+
+    // The history must run from the joined board to the current one
+    if data.joined != player.joined_state || data.board != player.current_state {
+        return Err(ChainError::HistoryMismatch);
+    }
+
+    // It must also fold every report the fleet has recorded on the way
+    if data.reports != recorded {
+        return Err(ChainError::ReportCountMismatch {
+            proven: data.reports,
+            recorded,
+        });
+    }
+
+    let msg = format!(
+        "\
+        \x20 History proven!\n\
+        \x20 ▶ Fleet {} in game {} reached its board through {} verified reports.\n\n",
+        data.fleet, data.gameid, data.reports
+    );
+    shared.bus.log(msg);
+
+    Ok(())
+}
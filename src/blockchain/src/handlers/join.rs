@@ -1,88 +1,143 @@
-use crate::{xy_pos, Game, Player, SharedData};
-use fleetcore::{BaseJournal, CommunicationData, EncryptedToken, SignedMessage};
+use crate::contract::GameContract;
+use crate::states::GamePhase;
+use crate::{Game, Player, SharedData};
+use fleetcore::{
+    commit_turn_seed, BaseJournal, ChainError, Command, CommunicationData, GameEvent,
+    COMMITMENT_VERSION,
+};
 use methods::JOIN_ID;
-use std::{collections::HashMap, sync::Mutex};
+use rand::RngCore;
+use risc0_zkvm::Digest;
+use std::collections::HashMap;
 
-pub fn handle_join(
+pub fn handle_join<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
+) -> Result<(), ChainError> {
     // Verify proof
     if input_data.receipt.verify(JOIN_ID).is_err() {
         shared
-            .tx
-            .send("Attempting to join game with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
+            .bus
+            .log("Attempting to join game with invalid receipt");
+        return Err(ChainError::InvalidReceipt { cmd: Command::Join });
     }
 
     // Decode journal
-    let data: BaseJournal = input_data.receipt.journal.decode().unwrap();
+    let data: BaseJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
 
-    // Extract token info (if present)
-    let (token_hash_opt, enc_token_opt, rsa_pubkey_opt) = match &input_data.token_data {
-        Some(t) => (
-            Some(t.token_hash),
-            Some(t.enc_token.clone()),
-            Some(t.pub_rsa_key.clone()),
-        ),
-        None => (None, None, None),
-    };
+    // Extract the player's RSA key, used later to hand them the turn token
+    let rsa_pubkey = input_data
+        .token_data
+        .as_ref()
+        .map(|t| t.pub_rsa_key.clone())
+        .unwrap_or_default();
 
-    // Access or initialize game. The 1st player joining has the turn.
+    // Access or initialize game. The 1st player joining creates it and fixes its player limits.
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = gmap.entry(data.gameid.clone()).or_insert_with(|| Game {
-        pmap: HashMap::new(),
-        shot_position: None,
-        pending_win: None,
-        encrypted_token: enc_token_opt.clone(),
-        turn_commitment: token_hash_opt,
-    });
+    if !gmap.contains_key(&data.gameid) {
+        // New games only accept the current board commitment scheme
+        if data.commitment_version != COMMITMENT_VERSION {
+            return Err(ChainError::CommitmentVersionMismatch {
+                expected: COMMITMENT_VERSION,
+                got: data.commitment_version,
+            });
+        }
+
+        let config = input_data.game_config.clone().unwrap_or_default();
+        if config.min_players < 2 || config.min_players > config.max_players {
+            return Err(ChainError::InvalidPlayerLimits {
+                min: config.min_players,
+                max: config.max_players,
+            });
+        }
+        config
+            .rules
+            .check()
+            .map_err(|reason| ChainError::InvalidRules { reason })?;
 
-    //println!("reg {:?}\nmeu {:?}", game.turn_commitment, token_hash_opt);
+        // The creator's fleet must have been validated against these rules.
+        // Later joiners are held to them when authenticated.
+        if data.rules != config.rules.digest() {
+            return Err(ChainError::RulesMismatch);
+        }
+
+        // Draw the turn-order seed now and publish only its commitment, so it is
+        // fixed before anyone joins and revealed when the game starts
+        let mut seed = [0u8; 32];
+        shared.rng.lock().unwrap().fill_bytes(&mut seed);
+        let turn_seed = Digest::from(seed);
 
-    // Prevent joining mid-game
-    if game.shot_position.is_some() {
-        return format!(
-            "Trying to join a game that's already ended! Game ID: {}, Players: [{}]",
-            data.gameid,
-            game.pmap.keys().cloned().collect::<Vec<_>>().join(", ")
+        gmap.insert(
+            data.gameid.clone(),
+            Game {
+                phase: GamePhase::Lobby,
+                creator: data.fleet.clone(),
+                config,
+                commitment_version: data.commitment_version,
+                pmap: HashMap::new(),
+                order: Vec::new(),
+                turn_seed,
+                turn_seed_commitment: commit_turn_seed(&turn_seed),
+                pending_shot: None,
+                pending_win: None,
+                winner: None,
+                reveals: HashMap::new(),
+                encrypted_token: None,
+                turn_commitment: None,
+                turn_holder: None,
+                turn_deadline: None,
+            },
         );
     }
+    let game = gmap.get_mut(&data.gameid).unwrap();
 
-    // Prevent joining game flagged as won
-    if game.pending_win.is_some() {
-        return format!("Trying to join a game that's already ended!",);
-    }
+    // Players may only join while the game is in the lobby
+    let next_phase = contract.transition(game.phase, Command::Join)?;
 
     // Check for duplicate players
     if let Some(existing_player) = game.pmap.get(&data.fleet) {
         if existing_player.public_key != public_key {
-            return format!(
-                "Public key mismatch for player \"{}\" in game \"{}\"",
-                data.fleet, data.gameid
-            );
+            return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
         }
 
-        return format!(
-            "Player \"{}\" is already in game \"{}\".\nCurrent players: [{}]",
-            data.fleet,
-            data.gameid,
-            game.pmap.keys().cloned().collect::<Vec<_>>().join(", ")
-        );
+        return Err(ChainError::AlreadyJoined {
+            fleet: data.fleet,
+            gameid: data.gameid,
+        });
+    }
+
+    // Enforce the player limit
+    if game.pmap.len() >= game.config.max_players {
+        return Err(ChainError::GameFull {
+            gameid: data.gameid,
+            max: game.config.max_players,
+        });
     }
 
     // Add player to the game
+    game.phase = next_phase;
     game.pmap.insert(
         data.fleet.clone(),
         Player {
             name: data.fleet.clone(),
-            current_state: data.board.clone(),
+            current_state: data.board,
+            joined_state: data.board,
             public_key: public_key.to_vec(),
-            rsa_pubkey: rsa_pubkey_opt.unwrap_or_default(),
+            rsa_pubkey,
+            strikes: 0,
+            forfeited: false,
+            eliminated: false,
+            shot_log: Digest::ZERO,
+            shots_taken: 0,
         },
     );
+    game.order.push(data.fleet.clone());
 
     // Format success message
     let players: Vec<String> = game.pmap.keys().cloned().collect();
@@ -93,17 +148,28 @@ pub fn handle_join(
         \x20 ▶ Fleet ID: {}\n\
         \x20 ▶ Commitment Hash: {:?}\n\n\
         \x20 Player \"{}\" joined game \"{}\".\n\
-        \x20 ▶ Total players: {}\n\
-        \x20 ▶ Current players: [{}]\n\n",
+        \x20 ▶ Total players: {} (min {}, max {})\n\
+        \x20 ▶ Current players: [{}]\n\
+        \x20 ▶ Waiting for {} to start the game\n\n",
         data.gameid,
         data.fleet,
         data.board,
         data.fleet,
         data.gameid,
         players.len(),
-        players.join(", ")
+        game.config.min_players,
+        game.config.max_players,
+        players.join(", "),
+        game.creator
+    );
+    shared.bus.emit(
+        &data.gameid,
+        GameEvent::PlayerJoined {
+            fleet: data.fleet.clone(),
+            players: players.len(),
+        },
     );
-    shared.tx.send(msg.replace('\n', "<br>")).unwrap();
+    shared.bus.log(msg);
 
-    "OK".to_string()
+    Ok(())
 }
@@ -1,13 +1,21 @@
 pub mod contest;
+pub mod defeat;
 pub mod fire;
+pub mod history;
 pub mod join;
 pub mod report;
+pub mod reveal;
+pub mod start;
 pub mod wave;
 pub mod win;
 
 pub use contest::handle_contest;
+pub use defeat::handle_defeat;
 pub use fire::handle_fire;
+pub use history::handle_history;
 pub use join::handle_join;
 pub use report::handle_report;
+pub use reveal::handle_reveal;
+pub use start::handle_start;
 pub use wave::handle_wave;
 pub use win::handle_win;
@@ -1,92 +1,191 @@
-use crate::{xy_pos, Game, Player, SharedData};
-use fleetcore::{CommunicationData, EncryptedToken, ReportJournal, SignedMessage};
-use methods::REPORT_ID;
-
-use std::{
-    collections::HashMap,
-    error::Error,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
+use crate::contract::GameContract;
+use crate::{xy_pos, SharedData};
+use fleetcore::{
+    ChainError, Command, CommunicationData, EncryptedToken, GameEvent, GameMode, GameRules,
+    Position, ReportJournal, SalvoReportJournal,
 };
+use methods::{REPORT_ID, SALVO_REPORT_ID};
 
-pub fn handle_report(
+pub fn handle_report<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
-    if input_data.receipt.verify(REPORT_ID).is_err() {
-        shared
-            .tx
-            .send("Attempting to report with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
-    }
-
-    // Decode journal
-    let data: ReportJournal = input_data.receipt.journal.decode().unwrap();
+) -> Result<(), ChainError> {
+    // A salvo is answered by a single batched report; a classic shot by a single one.
+    // Both are handled as a batch from here on.
+    let (mode, data) = if input_data.receipt.verify(REPORT_ID).is_ok() {
+        let report: ReportJournal = input_data
+            .receipt
+            .journal
+            .decode()
+            .map_err(|_| ChainError::MalformedPayload)?;
+        (GameMode::Classic, report.into())
+    } else if input_data.receipt.verify(SALVO_REPORT_ID).is_ok() {
+        let batch: SalvoReportJournal = input_data
+            .receipt
+            .journal
+            .decode()
+            .map_err(|_| ChainError::MalformedPayload)?;
+        (GameMode::Salvo, batch)
+    } else {
+        shared.bus.log("Attempting to report with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::Report,
+        });
+    };
 
     // Confirm game exists
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = match gmap.get_mut(&data.gameid) {
-        Some(g) => g,
-        None => return format!("Game {} not found", data.gameid),
-    };
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Report)?;
+
+    // The proof must fit the game mode
+    if mode != game.config.mode {
+        return Err(ChainError::ModeMismatch {
+            mode: game.config.mode,
+        });
+    }
 
     // Validate player's turn
     if game.turn_commitment != Some(data.token_commitment) {
-        return "Invalid token: not your turn.".to_string();
+        return Err(ChainError::NotYourTurn);
     }
 
-    // Confirm firing player exists and is valid
-    let player = match game.pmap.get_mut(&data.fleet) {
-        Some(p) => p,
-        None => return format!("Player {} not found in game {}", data.fleet, data.gameid),
-    };
+    // Only the fleet that was fired upon can answer
+    let pending = game
+        .pending_shot
+        .clone()
+        .ok_or_else(|| ChainError::IllegalCommand {
+            cmd: Command::Report,
+            phase: game.phase.to_string(),
+        })?;
+    if data.fleet != pending.target {
+        return Err(ChainError::NotTarget {
+            target: pending.target,
+        });
+    }
+
+    // The turn token must go to a fleet still in this game
+    let token_data: &EncryptedToken = input_data
+        .token_data
+        .as_ref()
+        .ok_or(ChainError::MissingToken)?;
+    match game.fleet_by_rsa_key(&token_data.pub_rsa_key) {
+        None => return Err(ChainError::InvalidTokenRecipient),
+        Some(fleet) if !game.pmap[&fleet].is_active() => {
+            return Err(ChainError::InactiveRecipient { fleet })
+        }
+        Some(_) => {}
+    }
+
+    // Confirm reporting player exists and is valid
+    let player = game
+        .pmap
+        .get_mut(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
 
     // Confirm public key matches
     if player.public_key != public_key {
-        return format!("Public key mismatch for player {}", data.fleet);
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
     }
 
-    // Make sure the shot advertised by the player is correct
-    if game.shot_position != Some(data.pos) {
-        return format!(
-            "Shot {} is not the shot fired by adversary ({})",
-            xy_pos(Some(data.pos)),
-            xy_pos(game.shot_position)
-        );
+    // Make sure the shots advertised by the player are the ones fired
+    if pending.shots != data.shots {
+        return Err(ChainError::ShotMismatch {
+            reported: labels(&data.shots, &game.config.rules),
+            fired: labels(&pending.shots, &game.config.rules),
+        });
     }
 
     // Validate that the stored commitment matches the one in the proof
     if player.current_state != data.board {
-        return format!("Reported board does not match stored commitment");
+        return Err(ChainError::CommitmentMismatch);
     }
 
     // Update the stored board state
-    player.current_state = data.next_board.clone();
+    player.current_state = data.next_board;
 
     // Update turn order
-    let token_data: &EncryptedToken = input_data.token_data.as_ref().unwrap();
-    game.encrypted_token = Some(token_data.enc_token.clone());
-    game.turn_commitment = Some(token_data.token_hash.clone());
-    game.shot_position = None;
+    game.pass_turn(token_data, shared.config.turn_timeout);
+    game.pending_shot = None;
+    game.phase = next_phase;
 
     // Emit a formatted message
+    let outcomes: Vec<String> = data
+        .shots
+        .iter()
+        .zip(&data.reports)
+        .map(|(&pos, report)| {
+            format!(
+                "{} at position {}",
+                report,
+                xy_pos(Some(pos), &game.config.rules)
+            )
+        })
+        .collect();
     let msg = format!(
         "\
         \x20 Report received.\n\
-        \x20 ▶ Player {} reported {} at position {}\n\
+        \x20 ▶ Player {} reported {}\n\
         \x20 ▶ Commitment updated.\n\
         \x20 ▶ {} is now allowed to fire.\n\n\n\
         \x20",
         data.fleet,
-        data.report,
-        xy_pos(Some(data.pos)),
-        data.fleet,
+        outcomes.join(", "),
+        game.turn_holder.as_deref().unwrap_or_default(),
     );
 
-    let html_msg = msg.replace('\n', "<br>");
-    shared.tx.send(html_msg.clone()).unwrap();
+    for (&pos, report) in data.shots.iter().zip(&data.reports) {
+        shared.bus.emit(
+            &data.gameid,
+            GameEvent::ShotReported {
+                fleet: data.fleet.clone(),
+                pos,
+                report: report.clone(),
+            },
+        );
+
+        // The proof disclosed the class of a ship that went down
+        if let Some(ship) = report
+            .strip_prefix("Sunk(")
+            .and_then(|r| r.strip_suffix(')'))
+        {
+            shared.bus.emit(
+                &data.gameid,
+                GameEvent::ShipSunk {
+                    fleet: data.fleet.clone(),
+                    ship: ship.to_string(),
+                },
+            );
+        }
+    }
+    if let Some(holder) = &game.turn_holder {
+        shared
+            .bus
+            .emit(&data.gameid, GameEvent::TurnPassed { to: holder.clone() });
+    }
+
+    shared.bus.log(msg);
 
-    "OK".to_string()
+    Ok(())
+}
+
+/// Cell names of a list of shots, for error messages.
+fn labels(shots: &[Position], rules: &GameRules) -> String {
+    let names: Vec<String> = shots.iter().map(|&pos| xy_pos(Some(pos), rules)).collect();
+    if names.is_empty() {
+        "None".to_string()
+    } else {
+        names.join(", ")
+    }
 }
@@ -0,0 +1,196 @@
+use crate::contract::GameContract;
+use crate::states::Reveal;
+use crate::{Player, SharedData};
+use fleetcore::{
+    commit_board, ship_class, ChainError, Command, CommunicationData, Fleet, GameConfig, GameEvent,
+    GameMode, ReportJournal, RevealJournal, SalvoReportJournal,
+};
+use methods::REVEAL_ID;
+use risc0_zkvm::Receipt;
+
+/// Accepts a fleet's board and nonce once the game is over, and audits them
+/// against the fleet's join commitment and every report it made. Mismatches
+/// are recorded with the board rather than rejected, so replay viewers see them.
+pub fn handle_reveal<C: GameContract + ?Sized>(
+    contract: &C,
+    shared: &SharedData,
+    input_data: &CommunicationData,
+    public_key: &[u8],
+) -> Result<(), ChainError> {
+    if input_data.receipt.verify(REVEAL_ID).is_err() {
+        shared
+            .bus
+            .log("Attempting to reveal a board with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::Reveal,
+        });
+    }
+
+    // Decode journal
+    let data: RevealJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // The fleet's report trail, in ledger order. Collected before the game is
+    // locked, as sealing a block locks the ledger first.
+    let trail: Vec<Receipt> = shared
+        .ledger
+        .lock()
+        .unwrap()
+        .history(&data.gameid)
+        .filter(|(_, tx)| tx.fleet == data.fleet && tx.cmd == Command::Report)
+        .map(|(_, tx)| tx.signed.payload.receipt.clone())
+        .collect();
+
+    // Confirm game exists
+    let mut gmap = shared.gmap.lock().unwrap();
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    contract.transition(game.phase, Command::Reveal)?;
+
+    // Confirm revealing player exists and is valid
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Confirm public key matches
+    if player.public_key != public_key {
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
+    }
+
+    // A board is opened once
+    if game.reveals.contains_key(&data.fleet) {
+        return Err(ChainError::AlreadyRevealed { fleet: data.fleet });
+    }
+
+    // The audit walks the board ship by ship against the game's fleet
+    let expected = game.config.rules.ships.len();
+    if data.board.ships.len() != expected {
+        return Err(ChainError::FleetShapeMismatch {
+            ships: data.board.ships.len(),
+            expected,
+        });
+    }
+
+    let mismatches = audit(&data, player, &trail, &game.config);
+    let honest = mismatches.is_empty();
+
+    let mut msg = format!(
+        "\
+        \x20 Fleet {} revealed its board in game {}.\n",
+        data.fleet, data.gameid
+    );
+    if honest {
+        msg += "\x20 ▶ Every commitment and report checks out.\n";
+    }
+    for mismatch in &mismatches {
+        msg += &format!("\x20 ▶ Mismatch: {}\n", mismatch);
+    }
+
+    game.reveals.insert(
+        data.fleet.clone(),
+        Reveal {
+            board: data.board,
+            random: data.random,
+            mismatches,
+        },
+    );
+
+    shared.bus.emit(
+        &data.gameid,
+        GameEvent::BoardRevealed {
+            fleet: data.fleet.clone(),
+            honest,
+        },
+    );
+    shared.bus.log(msg + "\n\n");
+
+    Ok(())
+}
+
+/// Replays the opened board through the fleet's join commitment and report
+/// trail, describing every step it does not reproduce.
+fn audit(
+    opening: &RevealJournal,
+    player: &Player,
+    trail: &[Receipt],
+    config: &GameConfig,
+) -> Vec<String> {
+    let rules = &config.rules;
+    let commit = |board: &Fleet| commit_board(board, &opening.random);
+    let mut mismatches = Vec::new();
+
+    let mut board = opening.board.clone();
+    if commit(&board) != player.joined_state {
+        mismatches.push("the board does not open the join commitment".to_string());
+    }
+
+    for (i, receipt) in trail.iter().enumerate() {
+        let step = i + 1;
+        let decoded = match config.mode {
+            GameMode::Classic => receipt
+                .journal
+                .decode::<ReportJournal>()
+                .map(SalvoReportJournal::from),
+            GameMode::Salvo => receipt.journal.decode::<SalvoReportJournal>(),
+        };
+        let report = match decoded {
+            Ok(report) => report,
+            Err(_) => {
+                mismatches.push(format!("report {} cannot be read", step));
+                continue;
+            }
+        };
+
+        if commit(&board) != report.board {
+            mismatches.push(format!("report {} starts from a different board", step));
+        }
+
+        // Apply the shots as the report proofs do
+        for (&pos, claimed) in report.shots.iter().zip(&report.reports) {
+            let outcome = match board.ship_at(pos) {
+                Some(ship) => {
+                    board.ships[ship].retain(|&p| p != pos);
+                    match rules.ships.get(ship) {
+                        Some(&len) if board.ships[ship].is_empty() => {
+                            format!("Sunk({})", ship_class(len))
+                        }
+                        Some(_) => "Hit".to_string(),
+                        None => format!("ship {} is not in the game's fleet", ship),
+                    }
+                }
+                None => "Miss".to_string(),
+            };
+            if *claimed != outcome {
+                mismatches.push(format!(
+                    "report {} claimed {} at {}, the board says {}",
+                    step,
+                    claimed,
+                    rules.label(pos),
+                    outcome
+                ));
+            }
+        }
+
+        if commit(&board) != report.next_board {
+            mismatches.push(format!("report {} leads to a different board", step));
+        }
+    }
+
+    if commit(&board) != player.current_state {
+        mismatches.push("the board does not lead to the final commitment".to_string());
+    }
+
+    mismatches
+}
@@ -0,0 +1,127 @@
+use crate::contract::GameContract;
+use crate::tokens::issue_turn_token;
+use crate::SharedData;
+use fleetcore::{turn_order, BaseJournal, ChainError, Command, CommunicationData, GameEvent};
+use methods::START_ID;
+use std::time::SystemTime;
+
+pub fn handle_start<C: GameContract + ?Sized>(
+    contract: &C,
+    shared: &SharedData,
+    input_data: &CommunicationData,
+    public_key: &[u8],
+) -> Result<(), ChainError> {
+    if input_data.receipt.verify(START_ID).is_err() {
+        shared
+            .bus
+            .log("Attempting to start game with invalid receipt");
+        return Err(ChainError::InvalidReceipt {
+            cmd: Command::Start,
+        });
+    }
+
+    // Decode journal
+    let data: BaseJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // Confirm game exists
+    let mut gmap = shared.gmap.lock().unwrap();
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Start)?;
+
+    // Only the creator may close the lobby
+    if data.fleet != game.creator {
+        return Err(ChainError::NotCreator {
+            creator: game.creator.clone(),
+        });
+    }
+
+    // Confirm starting player exists and is valid
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Confirm public key matches
+    if player.public_key != public_key {
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
+    }
+
+    // Validate commitment hash
+    if data.board != player.current_state {
+        return Err(ChainError::CommitmentMismatch);
+    }
+
+    // Make sure the roster is large enough
+    if game.pmap.len() < game.config.min_players {
+        return Err(ChainError::NotEnoughPlayers {
+            joined: game.pmap.len(),
+            needed: game.config.min_players,
+        });
+    }
+
+    // Reveal the seed and draw the turn order from it. Every fleet's draw also
+    // covers the board it joined with, so anyone can recompute the order.
+    let fleets: Vec<(String, _)> = game
+        .order
+        .iter()
+        .map(|id| (id.clone(), game.pmap[id].joined_state))
+        .collect();
+    game.order = turn_order(&game.turn_seed, &fleets);
+    game.phase = next_phase;
+
+    // The first fleet in the order gets a token issued by the chain
+    let first = game.order[0].clone();
+    let mut msg = format!(
+        "\
+        \x20 Game {} started by {}!\n\
+        \x20 ▶ Turn order: [{}]\n",
+        data.gameid,
+        data.fleet,
+        game.order.join(", ")
+    );
+    let mut passed_to = None;
+    match issue_turn_token(shared, &game.pmap[&first].rsa_pubkey) {
+        Ok(token) => {
+            game.pass_turn(&token, shared.config.turn_timeout);
+            msg += &format!("\x20 ▶ {} has the first turn.\n", first);
+            passed_to = Some(first);
+        }
+        // The turn timeout will hand it out instead
+        Err(e) => {
+            eprintln!(
+                "Failed to issue a turn token in game {}: {}",
+                data.gameid, e
+            );
+            game.turn_deadline = Some(SystemTime::now() + shared.config.turn_timeout);
+        }
+    }
+
+    shared.bus.emit(
+        &data.gameid,
+        GameEvent::TurnOrderFixed {
+            order: game.order.clone(),
+            seed: game.turn_seed,
+        },
+    );
+    if let Some(first) = passed_to {
+        shared
+            .bus
+            .emit(&data.gameid, GameEvent::TurnPassed { to: first });
+    }
+    shared.bus.log(msg + "\n\n");
+
+    Ok(())
+}
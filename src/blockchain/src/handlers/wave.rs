@@ -1,81 +1,94 @@
-use crate::{xy_pos, Game, Player, SharedData};
-use fleetcore::{BaseJournal, CommunicationData, EncryptedToken};
+use crate::contract::GameContract;
+use crate::SharedData;
+use fleetcore::{BaseJournal, ChainError, Command, CommunicationData, EncryptedToken, GameEvent};
 use methods::WAVE_ID;
 
-use std::{
-    collections::HashMap,
-    error::Error,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-};
-
-pub fn handle_wave(
+pub fn handle_wave<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
+) -> Result<(), ChainError> {
     if input_data.receipt.verify(WAVE_ID).is_err() {
         shared
-            .tx
-            .send("Attempting to wave game with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
+            .bus
+            .log("Attempting to wave game with invalid receipt");
+        return Err(ChainError::InvalidReceipt { cmd: Command::Wave });
     }
     // Decode journal
-    let data: BaseJournal = input_data.receipt.journal.decode().unwrap();
+    let data: BaseJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
 
     // Confirm game exists
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = match gmap.get_mut(&data.gameid) {
-        Some(g) => g,
-        None => return format!("Game {} not found", data.gameid),
-    };
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
+
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Wave)?;
 
     // Validate player's turn
     if game.turn_commitment != Some(data.token_commitment) {
-        return "Invalid token: not your turn.".to_string();
-    }
-
-    // Verify if the player has reported before firing
-    if game.shot_position.is_some() {
-        return "You must report the last shot before firing.\n".to_string();
+        return Err(ChainError::NotYourTurn);
     }
 
     // Confirm firing player exists and is valid
-    let player = match game.pmap.get_mut(&data.fleet) {
-        Some(p) => p,
-        None => return format!("Player {} not found in game {}", data.fleet, data.gameid),
-    };
+    let player = game
+        .pmap
+        .get_mut(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
 
     // Confirm public key matches
     if player.public_key != public_key {
-        return format!("Public key mismatch for player {}", data.fleet);
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
     }
 
     // Validate commitment hash
     if data.board != player.current_state {
-        return "Fleet commitment does not match recorded state\n\n\n\
-                \x20"
-            .to_string();
+        return Err(ChainError::CommitmentMismatch);
     }
 
+    // The named recipient must be another fleet in the game; defeated and
+    // forfeited fleets are skipped
+    let recipient = input_data
+        .recipient
+        .clone()
+        .ok_or(ChainError::MissingRecipient)?;
+    if recipient == data.fleet {
+        return Err(ChainError::SelfRecipient);
+    }
+    let recipient_key = match game.pmap.get(&recipient) {
+        None => {
+            return Err(ChainError::PlayerNotFound {
+                fleet: recipient,
+                gameid: data.gameid.clone(),
+            })
+        }
+        Some(p) if !p.is_active() => {
+            return Err(ChainError::InactiveRecipient { fleet: recipient })
+        }
+        Some(p) => p.rsa_pubkey.clone(),
+    };
+
     // Update turn order
-    let token_data: &EncryptedToken = input_data.token_data.as_ref().unwrap();
-    game.encrypted_token = Some(token_data.enc_token.clone());
-    game.turn_commitment = Some(token_data.token_hash.clone());
+    let token_data: &EncryptedToken = match input_data.token_data.as_ref() {
+        Some(t) if t.pub_rsa_key == recipient_key => t,
+        Some(_) => return Err(ChainError::InvalidTokenRecipient),
+        None => return Err(ChainError::MissingToken),
+    };
+    game.pass_turn(token_data, shared.config.turn_timeout);
+    game.phase = next_phase;
 
     // Build message
-    let recipient = input_data
-        .token_data
-        .as_ref()
-        .and_then(|t| {
-            game.pmap
-                .iter()
-                .find(|(_, p)| p.rsa_pubkey == t.pub_rsa_key)
-                .map(|(id, _)| id)
-        })
-        .cloned()
-        .unwrap_or_else(|| "(unknown recipient)".to_string());
 
     let msg = format!(
         "\
@@ -85,8 +98,13 @@ pub fn handle_wave(
         data.fleet, recipient,
     );
 
-    let html_msg = msg.replace('\n', "<br>");
-    shared.tx.send(html_msg.clone()).unwrap();
+    if let Some(holder) = &game.turn_holder {
+        shared
+            .bus
+            .emit(&data.gameid, GameEvent::TurnPassed { to: holder.clone() });
+    }
+
+    shared.bus.log(msg);
 
-    "OK".to_string()
+    Ok(())
 }
@@ -1,84 +1,106 @@
+use crate::contract::GameContract;
 use crate::states::PendingWin;
-use crate::{xy_pos, Game, Player, SharedData};
-use fleetcore::{BaseJournal, CommunicationData};
+use crate::SharedData;
+use fleetcore::{ChainError, Command, CommunicationData, GameEvent, WinJournal};
 use methods::WIN_ID;
-use std::time::Instant;
+use std::time::SystemTime;
 
-use std::{
-    collections::HashMap,
-    error::Error,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-};
-
-pub fn handle_win(
+pub fn handle_win<C: GameContract + ?Sized>(
+    contract: &C,
     shared: &SharedData,
     input_data: &CommunicationData,
     public_key: &[u8],
-) -> String {
+) -> Result<(), ChainError> {
     if input_data.receipt.verify(WIN_ID).is_err() {
-        shared
-            .tx
-            .send("Attempting victory with invalid receipt".to_string())
-            .unwrap();
-        return "Could not verify receipt".to_string();
+        shared.bus.log("Attempting victory with invalid receipt");
+        return Err(ChainError::InvalidReceipt { cmd: Command::Win });
     }
 
     // Decode journal
-    let data: BaseJournal = input_data.receipt.journal.decode().unwrap();
+    let data: WinJournal = input_data
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
 
     // Confirm game exists
     let mut gmap = shared.gmap.lock().unwrap();
-    let game = match gmap.get_mut(&data.gameid) {
-        Some(g) => g,
-        None => return format!("Game {} not found\n", data.gameid),
-    };
+    let game = gmap
+        .get_mut(&data.gameid)
+        .ok_or_else(|| ChainError::GameNotFound {
+            gameid: data.gameid.clone(),
+        })?;
 
-    // Only check shot_position if turn token is from the shot player
-    if game.turn_commitment == Some(data.token_commitment) {
-        // Verify if the player has reported before firing
-        if game.shot_position.is_some() {
-            return "You must report the last shot before firing.\n".to_string();
-        }
-    }
+    // Validate the command against the game's lifecycle
+    let next_phase = contract.transition(game.phase, Command::Win)?;
 
-    // Confirm firing player exists and is valid
-    let player = match game.pmap.get_mut(&data.fleet) {
-        Some(p) => p,
-        None => return format!("Player {} not found in game {}", data.fleet, data.gameid),
-    };
+    // Confirm claiming player exists and is valid
+    let player = game
+        .pmap
+        .get(&data.fleet)
+        .ok_or_else(|| ChainError::PlayerNotFound {
+            fleet: data.fleet.clone(),
+            gameid: data.gameid.clone(),
+        })?;
 
     // Confirm public key matches
     if player.public_key != public_key {
-        return format!("Public key mismatch for player {}", data.fleet);
+        return Err(ChainError::PublicKeyMismatch { fleet: data.fleet });
     }
 
     // Validate commitment hash
     if data.board != player.current_state {
-        return "Fleet commitment does not match recorded state\n\n\n\
-         \x20"
-            .to_string();
+        return Err(ChainError::CommitmentMismatch);
+    }
+
+    // Every opponent must be out of the game
+    let remaining: Vec<String> = game
+        .active_players()
+        .into_iter()
+        .filter(|id| *id != data.fleet)
+        .collect();
+    if !remaining.is_empty() {
+        return Err(ChainError::OpponentsRemaining { fleets: remaining });
     }
 
-    // Check if there's a win claim already
-    if game.pending_win.is_some() {
-        return "There's a victory claim already<br>".to_string();
+    // The proven standings must be the ones the chain recorded
+    let standings = game.standings();
+    if data.standings != standings {
+        return Err(ChainError::StandingsMismatch);
     }
 
-    // Change the game state
+    // The claim stays open to contest until the window closes
+    game.phase = next_phase;
     game.pending_win = Some(PendingWin {
         claimant: data.fleet.clone(),
         board: data.board,
-        time: Instant::now(),
+        time: SystemTime::now(),
+        standings: data.standings.clone(),
     });
 
+    let table: Vec<String> = standings
+        .iter()
+        .map(|s| format!("\x20 ▶ {}: {:?}\n", s.fleet, s.status))
+        .collect();
     let msg = format!(
-        "Player {} has claimed victory in game {}!\nAnyone may now contest the claim.\n\n\
-        \x20",
-        data.fleet, data.gameid
+        "\
+        \x20 Player {} has claimed victory in game {}!\n\
+        \x20 ▶ Anyone may contest the claim within the next {} seconds.\n\n\
+        \x20 Standings:\n\
+        {}\n\n",
+        data.fleet,
+        data.gameid,
+        shared.config.contest_window.as_secs(),
+        table.concat()
+    );
+
+    shared.bus.emit(
+        &data.gameid,
+        GameEvent::WinClaimed {
+            claimant: data.fleet.clone(),
+        },
     );
-    let html_msg = msg.replace('\n', "<br>");
-    shared.tx.send(html_msg.clone()).unwrap();
+    shared.bus.log(msg);
 
-    "OK".to_string()
+    Ok(())
 }
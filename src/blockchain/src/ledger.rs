@@ -0,0 +1,276 @@
+//! Hash-linked block ledger recording every accepted transaction.
+//!
+//! Each block commits to its predecessor through `prev_hash` and to its
+//! transactions through a Merkle root, so a game can be audited after the fact
+//! by replaying the chain instead of trusting the live game map.
+
+use fleetcore::{Command, CommunicationData, SignedMessage};
+use risc0_zkvm::Digest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as ShaDigest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An accepted `SignedMessage` together with the metadata needed to index it.
+#[derive(Deserialize, Serialize)]
+pub struct Transaction {
+    pub hash: Digest,   // SHA-256 of the serialized signed message
+    pub cmd: Command,   // Command carried by the payload
+    pub gameid: String, // Game the transaction belongs to
+    pub fleet: String,  // Fleet that submitted it
+    pub signed: SignedMessage<CommunicationData>,
+}
+
+/// Fields covered by the block hash.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BlockHeader {
+    pub height: u64,
+    pub timestamp: u64,      // Seconds since the UNIX epoch
+    pub prev_hash: Digest,   // Hash of the previous block (zero for genesis)
+    pub merkle_root: Digest, // Merkle root over the transaction hashes
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Block {
+    pub hash: Digest,
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Append-only chain of blocks, starting from an empty genesis block.
+pub struct Ledger {
+    blocks: Vec<Block>,
+}
+
+impl Transaction {
+    /// Wraps an accepted signed message, hashing its serialized form.
+    pub fn new(gameid: String, fleet: String, signed: SignedMessage<CommunicationData>) -> Self {
+        Transaction {
+            hash: hash_signed(&signed),
+            cmd: signed.payload.cmd,
+            gameid,
+            fleet,
+            signed,
+        }
+    }
+}
+
+/// SHA-256 of a signed message's serialized form.
+fn hash_signed(signed: &SignedMessage<CommunicationData>) -> Digest {
+    let bytes = serde_json::to_vec(signed).expect("Signed message is serializable");
+    sha256(&[&bytes])
+}
+
+impl BlockHeader {
+    /// Computes `SHA256(height || timestamp || prev_hash || merkle_root)`.
+    pub fn hash(&self) -> Digest {
+        sha256(&[
+            &self.height.to_le_bytes(),
+            &self.timestamp.to_le_bytes(),
+            self.prev_hash.as_bytes(),
+            self.merkle_root.as_bytes(),
+        ])
+    }
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ledger {
+    /// Creates a ledger holding only the genesis block. The genesis timestamp is
+    /// fixed so every emulator instance derives the same genesis hash.
+    pub fn new() -> Self {
+        let mut ledger = Ledger { blocks: Vec::new() };
+        let genesis = ledger.block_on_head(Vec::new(), 0);
+        ledger.blocks.push(genesis);
+        ledger
+    }
+
+    /// Seals the given transactions into a block linked to the current head.
+    /// It only becomes part of the chain once passed to [`Ledger::append`].
+    pub fn seal(&self, transactions: Vec<Transaction>) -> Block {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.block_on_head(transactions, timestamp)
+    }
+
+    /// Adds a block sealed on the current head and returns it.
+    pub fn append(&mut self, block: Block) -> &Block {
+        debug_assert_eq!(block.header.prev_hash, self.head().hash);
+        self.blocks.push(block);
+        self.head()
+    }
+
+    /// Re-appends a block read back from storage. Links are not checked here;
+    /// call [`Ledger::verify`] once the whole chain has been restored.
+    pub fn restore(&mut self, block: Block) {
+        self.blocks.push(block);
+    }
+
+    fn block_on_head(&self, transactions: Vec<Transaction>, timestamp: u64) -> Block {
+        let header = BlockHeader {
+            height: self.blocks.len() as u64,
+            timestamp,
+            prev_hash: self.blocks.last().map(|b| b.hash).unwrap_or(Digest::ZERO),
+            merkle_root: merkle_root(transactions.iter().map(|tx| tx.hash).collect()),
+        };
+
+        Block {
+            hash: header.hash(),
+            header,
+            transactions,
+        }
+    }
+
+    pub fn head(&self) -> &Block {
+        self.blocks
+            .last()
+            .expect("Ledger always holds a genesis block")
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn block(&self, height: u64) -> Option<&Block> {
+        self.blocks.get(usize::try_from(height).ok()?)
+    }
+
+    /// Finds a transaction by its hex-encoded hash, returning it with the height of its block.
+    pub fn transaction(&self, hash: &str) -> Option<(u64, &Transaction)> {
+        self.blocks.iter().find_map(|block| {
+            block
+                .transactions
+                .iter()
+                .find(|tx| tx.hash.to_string() == hash)
+                .map(|tx| (block.header.height, tx))
+        })
+    }
+
+    /// Every transaction of a game in the order it was accepted, with the block holding it.
+    pub fn history<'a>(
+        &'a self,
+        gameid: &'a str,
+    ) -> impl Iterator<Item = (&'a Block, &'a Transaction)> {
+        self.blocks.iter().flat_map(move |block| {
+            block
+                .transactions
+                .iter()
+                .filter(move |tx| tx.gameid == gameid)
+                .map(move |tx| (block, tx))
+        })
+    }
+
+    /// Re-derives every transaction hash, block hash, back link and Merkle root.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the chain is intact
+    /// - `Err(height)` of the first inconsistent block otherwise
+    pub fn verify(&self) -> Result<(), u64> {
+        let mut prev_hash = Digest::ZERO;
+        for block in &self.blocks {
+            let tampered = block
+                .transactions
+                .iter()
+                .any(|tx| tx.hash != hash_signed(&tx.signed) || tx.cmd != tx.signed.payload.cmd);
+            if tampered {
+                return Err(block.header.height);
+            }
+
+            let root = merkle_root(block.transactions.iter().map(|tx| tx.hash).collect());
+            if block.header.prev_hash != prev_hash
+                || block.header.merkle_root != root
+                || block.header.hash() != block.hash
+            {
+                return Err(block.header.height);
+            }
+            prev_hash = block.hash;
+        }
+        Ok(())
+    }
+}
+
+/// Domain tags keeping leaves and inner nodes of the Merkle tree apart.
+const MERKLE_LEAF: &[u8] = &[0x00];
+const MERKLE_NODE: &[u8] = &[0x01];
+
+/// Computes a binary Merkle root over `SHA256(0x00 || leaf)` leaves, hashing
+/// pairs as `SHA256(0x01 || left || right)`. The last node of an odd level is
+/// carried up unpaired rather than duplicated, so no two transaction lists
+/// share a root. An empty transaction list yields the zero digest.
+pub fn merkle_root(leaves: Vec<Digest>) -> Digest {
+    if leaves.is_empty() {
+        return Digest::ZERO;
+    }
+
+    let mut level: Vec<Digest> = leaves
+        .iter()
+        .map(|leaf| sha256(&[MERKLE_LEAF, leaf.as_bytes()]))
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => sha256(&[MERKLE_NODE, left.as_bytes(), right.as_bytes()]),
+                [odd] => *odd,
+                _ => unreachable!("chunks(2) yields one or two nodes"),
+            })
+            .collect();
+    }
+
+    level[0]
+}
+
+fn sha256(parts: &[&[u8]]) -> Digest {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    Digest::try_from(hasher.finalize().as_slice()).expect("Hash size mismatch")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ledger(blocks: usize) -> Ledger {
+        let mut ledger = Ledger::new();
+        for _ in 0..blocks {
+            let block = ledger.seal(Vec::new());
+            ledger.append(block);
+        }
+        ledger
+    }
+
+    #[test]
+    fn intact_chain_verifies() {
+        assert_eq!(ledger(3).verify(), Ok(()));
+    }
+
+    #[test]
+    fn tampered_header_is_rejected() {
+        let mut ledger = ledger(3);
+        ledger.blocks[2].header.timestamp += 1;
+        assert_eq!(ledger.verify(), Err(2));
+    }
+
+    #[test]
+    fn broken_link_is_rejected() {
+        let mut ledger = ledger(3);
+        ledger.blocks[2].header.prev_hash = Digest::ZERO;
+        ledger.blocks[2].hash = ledger.blocks[2].header.hash();
+        assert_eq!(ledger.verify(), Err(2));
+    }
+
+    #[test]
+    fn merkle_root_does_not_collide_on_a_repeated_last_leaf() {
+        let leaves: Vec<Digest> = (0u8..3).map(|i| sha256(&[&[i]])).collect();
+        let mut repeated = leaves.clone();
+        repeated.push(leaves[2]);
+        assert_ne!(merkle_root(leaves), merkle_root(repeated));
+    }
+}
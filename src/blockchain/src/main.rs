@@ -5,51 +5,97 @@
 
 use futures::stream::StreamExt;
 use rand::{seq::IteratorRandom, SeedableRng};
-use risc0_zkvm::Digest;
+use risc0_zkvm::{Digest, Receipt};
 
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
 
 use axum::{
-    extract::{Extension, Query},
-    response::{sse::Event, sse::Sse, Html, IntoResponse},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, Path, Query,
+    },
+    http::StatusCode,
+    response::{sse::Event, sse::Sse, Html, IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 
 use std::{
     collections::HashMap,
+    convert::Infallible,
     error::Error,
     net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Instant, UNIX_EPOCH},
 };
 
 use serde::{Deserialize, Serialize};
 
 mod states;
-use states::{Game, Player, SharedData};
+use states::{ChainConfig, Game, GamePhase, PendingShot, Player, SharedData};
+
+mod ledger;
+use ledger::{BlockHeader, Transaction};
+
+mod store;
+use store::Store;
+
+mod events;
+use events::{EventBus, Held};
+
+mod replay;
+use replay::ReplayGuard;
+
+mod timers;
+use timers::spawn_timers;
+
+mod tokens;
+
+mod contract;
+use contract::{contract_for, ContractRegistry};
 
 mod handlers;
-use handlers::{handle_contest, handle_fire, handle_join, handle_report, handle_wave, handle_win};
 
 mod authenticate;
 use authenticate::{authenticate, verify_signature};
 
-use fleetcore::{Command, CommunicationData, SignedMessage};
+use fleetcore::{
+    ChainError, Command, CommunicationData, ErrorResponse, FireJournal, GameConfig, GameMode,
+    GameRules, JournalHeader, Position, SalvoJournal, Shot, SignedMessage,
+};
 
 use base64::{engine::general_purpose, Engine as _};
 
 #[tokio::main]
 async fn main() {
-    // Create a broadcast channel for log messages
-    let (tx, _rx) = broadcast::channel::<String>(100);
+    // Rebuild the chain state from the write-ahead log
+    let data_dir = std::env::var("CHAIN_DATA_DIR").unwrap_or_else(|_| "chain_data".to_string());
+    let (store, recovered) =
+        Store::open(&PathBuf::from(data_dir)).expect("Failed to open chain data store");
+    println!(
+        "Recovered {} blocks and {} games from {}",
+        recovered.ledger.blocks().len() - 1,
+        recovered.gmap.len(),
+        store.path().display()
+    );
+
+    let config = ChainConfig::from_env();
     let shared = SharedData {
-        tx: tx,
-        gmap: Arc::new(Mutex::new(HashMap::new())),
+        bus: Arc::new(EventBus::new(config.event_backlog, recovered.last_event)),
+        gmap: Arc::new(Mutex::new(recovered.gmap)),
         rng: Arc::new(Mutex::new(rand::rngs::StdRng::from_entropy())),
+        replay: Arc::new(Mutex::new(ReplayGuard::from_ledger(&recovered.ledger))),
+        contracts: Arc::new(ContractRegistry::default()),
+        ledger: Arc::new(Mutex::new(recovered.ledger)),
+        store: Arc::new(store),
+        config,
     };
 
+    // Settle expired victory claims and take the turn away from idle players
+    spawn_timers(shared.clone());
+
     // Build our application with a route
     let app = Router::new()
         .route("/", get(index))
@@ -58,6 +104,20 @@ async fn main() {
         .route("/key", get(get_rsa_key))
         .route("/players", get(get_player_list))
         .route("/token", get(get_token_data))
+        .route("/blocks", get(get_blocks))
+        .route("/blocks/:height", get(get_block))
+        .route("/tx/:hash", get(get_transaction))
+        .route("/verify", get(verify_ledger))
+        .route("/games", get(get_games))
+        .route("/games/:id", get(get_game))
+        .route("/games/:id/history", get(get_game_history))
+        .route("/games/:id/fleets/:fleet/receipts", get(get_fleet_receipts))
+        .route("/games/:id/fleets/:fleet/proof", get(get_history_proof))
+        .route("/games/:id/fleets/:fleet/shots", get(get_shot_log))
+        .route("/games/:id/standings", get(get_standings))
+        .route("/games/:id/rules", get(get_rules))
+        .route("/games/:id/reveals", get(get_reveals))
+        .route("/games/:id/events", get(game_events))
         .layer(Extension(shared));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
@@ -162,54 +222,520 @@ async fn get_player_list(
         None => return Json(vec![]),
     };
 
-    Json(game.pmap.keys().cloned().collect())
+    // Only fleets that can still be targeted or handed the turn
+    Json(game.active_players())
 }
 
 // Handler to manage SSE connections
 #[axum::debug_handler]
 async fn logs(Extension(shared): Extension<SharedData>) -> impl IntoResponse {
-    let rx = BroadcastStream::new(shared.tx.subscribe());
+    let rx = BroadcastStream::new(shared.bus.subscribe_logs());
 
-    let stream = rx.map(|result| match result {
-        Ok(msg) => Ok(Event::default().data(msg)),
-        Err(_) => Err(Box::<dyn Error + Send + Sync>::from("Error")),
-    });
+    // A subscriber that fell behind skips the lines it missed
+    let stream = rx
+        .filter_map(|result| async move { result.ok() })
+        .map(|msg| Ok::<_, Infallible>(Event::default().data(msg)));
 
     Sse::new(stream)
 }
 
+#[derive(Deserialize)]
+struct EventsQuery {
+    since: Option<u64>, // Last event id the client has seen
+}
+
+/// Upgrades to a WebSocket streaming the typed events of a single game as JSON.
+/// With `?since=<id>`, backlogged events after that id are sent first.
+async fn game_events(
+    ws: WebSocketUpgrade,
+    Extension(shared): Extension<SharedData>,
+    Path(id): Path<String>,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    ws.on_upgrade(move |socket| stream_game_events(socket, shared.bus, id, query.since))
+}
+
+async fn stream_game_events(
+    mut socket: WebSocket,
+    bus: Arc<EventBus>,
+    gameid: String,
+    since: Option<u64>,
+) {
+    let mut last = since.unwrap_or(0);
+    let (mut pending, mut rx) = bus.subscribe(&gameid, since);
+
+    loop {
+        // Deliver what the client has not seen yet, in id order
+        for record in pending.drain(..) {
+            if record.id <= last {
+                continue;
+            }
+            let json = serde_json::to_string(&record).expect("Events are serializable");
+            if socket.send(Message::Text(json)).await.is_err() {
+                return;
+            }
+            last = record.id;
+        }
+
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(record) if record.gameid == gameid => pending.push(record),
+                Ok(_) => {}
+                // Fell behind the channel; catch up from the backlog instead
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    (pending, rx) = bus.subscribe(&gameid, Some(last));
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Stop once the client goes away
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
 async fn smart_contract(
     Extension(shared): Extension<SharedData>,
     Json(signed): Json<SignedMessage<CommunicationData>>,
-) -> String {
-    if let Err(err) = authenticate(&shared, &signed) {
-        return err;
+) -> Response {
+    // Proof checks run under the replay lock, so keep them off the runtime
+    match tokio::task::spawn_blocking(move || execute(&shared, signed)).await {
+        Ok(Ok(())) => "OK".into_response(),
+        Ok(Err(err)) => error_response(&err),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// JSON body and HTTP status for a rejected request.
+fn error_response(err: &ChainError) -> Response {
+    let status = StatusCode::from_u16(err.status()).unwrap_or(StatusCode::BAD_REQUEST);
+    (status, Json(ErrorResponse::from(err))).into_response()
+}
+
+/// Authenticates, replay-checks and applies a transaction, recording it on success.
+fn execute(
+    shared: &SharedData,
+    signed: SignedMessage<CommunicationData>,
+) -> Result<(), ChainError> {
+    authenticate(shared, &signed)?;
+
+    // Held until the transaction is recorded, so a duplicate cannot slip in between
+    let mut replay = shared.replay.lock().unwrap();
+    if let Err(err) = replay.check(&signed) {
+        shared.bus.log(format!("Rejected transaction: {}", err));
+        return Err(err);
     }
 
     let input = &signed.payload;
     let pk = &signed.public_key;
 
-    match input.cmd {
-        Command::Join => handle_join(&shared, input, pk),
-        Command::Fire => handle_fire(&shared, input, pk),
-        Command::Report => handle_report(&shared, input, pk),
-        Command::Wave => handle_wave(&shared, input, pk),
-        Command::Win => handle_win(&shared, input, pk),
-        Command::Contest => handle_contest(&shared, input, pk),
+    // Every journal starts with the game and fleet IDs
+    let header: JournalHeader = input
+        .receipt
+        .journal
+        .decode()
+        .map_err(|_| ChainError::MalformedPayload)?;
+
+    // The game's own rules decide what the transaction does, on a copy of the game
+    let staged = shared.staged(&header.gameid);
+    if let Err(err) = contract_for(shared, input)?.apply(&staged, input, pk) {
+        // Explanations of the rejection are still worth showing
+        let mut held = staged.bus.take_held();
+        held.retain(|h| matches!(h, Held::Log(_)));
+        shared.bus.publish(held);
+        return Err(err);
+    }
+
+    record_transaction(shared, &staged, &mut replay, header, signed)
+}
+
+/// Seals an accepted transaction into a new block and writes it to the store
+/// together with the resulting game state. Only then are the block, the game
+/// and the spent nonce and receipt committed in memory, and the transaction's
+/// events and log lines published.
+fn record_transaction(
+    shared: &SharedData,
+    staged: &SharedData,
+    replay: &mut ReplayGuard,
+    header: JournalHeader,
+    signed: SignedMessage<CommunicationData>,
+) -> Result<(), ChainError> {
+    let gameid = header.gameid.clone();
+
+    let mut ledger = shared.ledger.lock().unwrap();
+    let block = ledger.seal(vec![Transaction::new(header.gameid, header.fleet, signed)]);
+
+    let mut gmap = shared.gmap.lock().unwrap();
+    let game = staged.gmap.lock().unwrap().remove(&gameid);
+    let held = staged.bus.take_held();
+    if let Some(game) = &game {
+        // Nothing else publishes while the replay lock is held
+        let events = held
+            .iter()
+            .filter(|h| matches!(h, Held::Event { .. }))
+            .count();
+        let last_event = shared.bus.last_id() + events as u64;
+        if let Err(e) = shared.store.append(Some(&block), &gameid, game, last_event) {
+            eprintln!("Failed to persist block #{}: {}", block.header.height, e);
+            return Err(ChainError::StorageFailure);
+        }
+    }
+
+    let block = ledger.append(block);
+    for tx in &block.transactions {
+        replay.consume(&tx.signed);
     }
+    if let Some(game) = game {
+        gmap.insert(gameid, game);
+    }
+    shared.bus.publish(held);
+
+    let msg = format!(
+        "\
+        \x20 ▶ Recorded in block #{} ({})\n\n",
+        block.header.height, block.hash
+    );
+    shared.bus.log(msg);
+
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// LEDGER QUERIES
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize)]
+struct BlockSummary {
+    hash: Digest,
+    header: BlockHeader,
+    transactions: Vec<Digest>,
+}
+
+/// Lists every block header together with the hashes of its transactions.
+async fn get_blocks(Extension(shared): Extension<SharedData>) -> Json<Vec<BlockSummary>> {
+    let ledger = shared.ledger.lock().unwrap();
+    Json(
+        ledger
+            .blocks()
+            .iter()
+            .map(|b| BlockSummary {
+                hash: b.hash,
+                header: b.header.clone(),
+                transactions: b.transactions.iter().map(|tx| tx.hash).collect(),
+            })
+            .collect(),
+    )
+}
+
+/// Returns a full block, including the signed transactions it contains.
+async fn get_block(
+    Extension(shared): Extension<SharedData>,
+    Path(height): Path<u64>,
+) -> impl IntoResponse {
+    let ledger = shared.ledger.lock().unwrap();
+    match ledger.block(height) {
+        Some(block) => Json(block).into_response(),
+        None => error_response(&ChainError::BlockNotFound { height }),
+    }
+}
+
+#[derive(Serialize)]
+struct TransactionRecord<'a> {
+    block: u64,
+    transaction: &'a Transaction,
+}
+
+/// Looks up a single transaction by its hex-encoded hash.
+async fn get_transaction(
+    Extension(shared): Extension<SharedData>,
+    Path(hash): Path<String>,
+) -> impl IntoResponse {
+    let ledger = shared.ledger.lock().unwrap();
+    match ledger.transaction(&hash) {
+        Some((block, transaction)) => {
+            Json(TransactionRecord { block, transaction }).into_response()
+        }
+        None => error_response(&ChainError::TransactionNotFound { hash }),
+    }
+}
+
+/// Re-derives the whole chain and reports the first inconsistent block, if any.
+async fn verify_ledger(Extension(shared): Extension<SharedData>) -> String {
+    let ledger = shared.ledger.lock().unwrap();
+    match ledger.verify() {
+        Ok(()) => format!("OK: {} blocks verified", ledger.blocks().len()),
+        Err(height) => format!("Ledger inconsistent at block {}", height),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// GAME QUERIES
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize)]
+struct GameSummary {
+    gameid: String,
+    phase: GamePhase,
+    players: usize,
+    winner: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlayerView {
+    name: String,
+    commitment: Digest, // Current board commitment
+    strikes: u32,
+    forfeited: bool,
+    eliminated: bool,
+    shots_taken: u32,
+}
+
+#[derive(Serialize)]
+struct WinView {
+    claimant: String,
+    board: Digest,
+    claimed_at: u64, // Seconds since the Unix epoch
+}
+
+#[derive(Serialize)]
+struct GameView {
+    gameid: String,
+    phase: GamePhase,
+    creator: String,
+    config: GameConfig,
+    commitment_version: u16,
+    players: Vec<PlayerView>, // In turn order
+    turn_seed_commitment: Digest,
+    turn_seed: Option<Digest>, // Revealed once the game has started
+    turn_holder: Option<String>,
+    pending_shot: Option<PendingShot>,
+    pending_win: Option<WinView>,
+    winner: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry {
+    block: u64,
+    timestamp: u64,
+    hash: Digest,
+    cmd: Command,
+    fleet: String,
+}
+
+/// A receipt that moved a fleet's board, as folded into its history proof.
+#[derive(Serialize)]
+struct FleetReceipt {
+    cmd: Command,
+    receipt: Receipt,
+}
+
+/// Lists every game with its phase and player count.
+async fn get_games(Extension(shared): Extension<SharedData>) -> Json<Vec<GameSummary>> {
+    let gmap = shared.gmap.lock().unwrap();
+    let mut games: Vec<GameSummary> = gmap
+        .iter()
+        .map(|(gameid, game)| GameSummary {
+            gameid: gameid.clone(),
+            phase: game.phase,
+            players: game.pmap.len(),
+            winner: game.winner.clone(),
+        })
+        .collect();
+    games.sort_by(|a, b| a.gameid.cmp(&b.gameid));
+    Json(games)
+}
+
+/// Returns the public state of a game: commitments, the shot and claim in flight, and the result.
+async fn get_game(Extension(shared): Extension<SharedData>, Path(id): Path<String>) -> Response {
+    let gmap = shared.gmap.lock().unwrap();
+    let game = match gmap.get(&id) {
+        Some(g) => g,
+        None => return error_response(&ChainError::GameNotFound { gameid: id }),
+    };
+
+    let players = game
+        .order
+        .iter()
+        .filter_map(|fleet| game.pmap.get(fleet))
+        .map(|p| PlayerView {
+            name: p.name.clone(),
+            commitment: p.current_state,
+            strikes: p.strikes,
+            forfeited: p.forfeited,
+            eliminated: p.eliminated,
+            shots_taken: p.shots_taken,
+        })
+        .collect();
+
+    let pending_win = game.pending_win.as_ref().map(|w| WinView {
+        claimant: w.claimant.clone(),
+        board: w.board,
+        claimed_at: w
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    });
+
+    Json(GameView {
+        gameid: id.clone(),
+        phase: game.phase,
+        creator: game.creator.clone(),
+        config: game.config.clone(),
+        commitment_version: game.commitment_version,
+        players,
+        turn_seed_commitment: game.turn_seed_commitment,
+        turn_seed: (game.phase != GamePhase::Lobby).then_some(game.turn_seed),
+        turn_holder: game.turn_holder.clone(),
+        pending_shot: game.pending_shot.clone(),
+        pending_win,
+        winner: game.winner.clone(),
+    })
+    .into_response()
+}
+
+/// Returns the status of every fleet in turn order, as a win proof must commit it.
+async fn get_standings(
+    Extension(shared): Extension<SharedData>,
+    Path(id): Path<String>,
+) -> Response {
+    let gmap = shared.gmap.lock().unwrap();
+    match gmap.get(&id) {
+        Some(game) => Json(game.standings()).into_response(),
+        None => error_response(&ChainError::GameNotFound { gameid: id }),
+    }
+}
+
+/// Boards opened after the game, by fleet, with the mismatches the chain found.
+async fn get_reveals(Extension(shared): Extension<SharedData>, Path(id): Path<String>) -> Response {
+    let gmap = shared.gmap.lock().unwrap();
+    match gmap.get(&id) {
+        Some(game) => Json(game.reveals.clone()).into_response(),
+        None => error_response(&ChainError::GameNotFound { gameid: id }),
+    }
+}
+
+/// Board and fleet rules of a game, needed by the host to build its proofs.
+async fn get_rules(Extension(shared): Extension<SharedData>, Path(id): Path<String>) -> Response {
+    let gmap = shared.gmap.lock().unwrap();
+    match gmap.get(&id) {
+        Some(game) => Json(game.config.rules.clone()).into_response(),
+        None => error_response(&ChainError::GameNotFound { gameid: id }),
+    }
+}
+
+/// Lists the accepted transactions of a game in ledger order.
+async fn get_game_history(
+    Extension(shared): Extension<SharedData>,
+    Path(id): Path<String>,
+) -> Response {
+    if !shared.gmap.lock().unwrap().contains_key(&id) {
+        return error_response(&ChainError::GameNotFound { gameid: id });
+    }
+
+    let ledger = shared.ledger.lock().unwrap();
+    let history: Vec<HistoryEntry> = ledger
+        .history(&id)
+        .map(|(block, tx)| HistoryEntry {
+            block: block.header.height,
+            timestamp: block.header.timestamp,
+            hash: tx.hash,
+            cmd: tx.cmd,
+            fleet: tx.fleet.clone(),
+        })
+        .collect();
+    Json(history).into_response()
+}
+
+/// Join and report receipts of a fleet in ledger order, for building its history proof.
+async fn get_fleet_receipts(
+    Extension(shared): Extension<SharedData>,
+    Path((id, fleet)): Path<(String, String)>,
+) -> Response {
+    if !shared.gmap.lock().unwrap().contains_key(&id) {
+        return error_response(&ChainError::GameNotFound { gameid: id });
+    }
+
+    let ledger = shared.ledger.lock().unwrap();
+    let receipts: Vec<FleetReceipt> = ledger
+        .history(&id)
+        .filter(|(_, tx)| tx.fleet == fleet && matches!(tx.cmd, Command::Join | Command::Report))
+        .map(|(_, tx)| FleetReceipt {
+            cmd: tx.cmd,
+            receipt: tx.signed.payload.receipt.clone(),
+        })
+        .collect();
+    Json(receipts).into_response()
+}
+
+/// Latest accepted history proof of a fleet. It verifies against the history
+/// image ID and commits the image IDs it trusted.
+async fn get_history_proof(
+    Extension(shared): Extension<SharedData>,
+    Path((id, fleet)): Path<(String, String)>,
+) -> Response {
+    if !shared.gmap.lock().unwrap().contains_key(&id) {
+        return error_response(&ChainError::GameNotFound { gameid: id });
+    }
+
+    let ledger = shared.ledger.lock().unwrap();
+    let proof = ledger
+        .history(&id)
+        .filter(|(_, tx)| tx.fleet == fleet && tx.cmd == Command::History)
+        .last()
+        .map(|(_, tx)| tx.signed.payload.receipt.clone());
+    match proof {
+        Some(receipt) => Json(receipt).into_response(),
+        None => error_response(&ChainError::NoHistoryProof { fleet }),
+    }
+}
+
+/// Every shot a fleet fired, in ledger order. Its hash chain is the shot log the
+/// chain keeps for the fleet, so the next fire proof can be built from it.
+async fn get_shot_log(
+    Extension(shared): Extension<SharedData>,
+    Path((id, fleet)): Path<(String, String)>,
+) -> Response {
+    let mode = match shared.gmap.lock().unwrap().get(&id) {
+        Some(game) => game.config.mode,
+        None => return error_response(&ChainError::GameNotFound { gameid: id }),
+    };
+
+    let ledger = shared.ledger.lock().unwrap();
+    let mut shots: Vec<Shot> = Vec::new();
+    for (_, tx) in ledger
+        .history(&id)
+        .filter(|(_, tx)| tx.fleet == fleet && tx.cmd == Command::Fire)
+    {
+        let journal = &tx.signed.payload.receipt.journal;
+        let fired = match mode {
+            GameMode::Classic => journal
+                .decode::<FireJournal>()
+                .map(|fire| (fire.target, vec![fire.pos])),
+            GameMode::Salvo => journal
+                .decode::<SalvoJournal>()
+                .map(|salvo| (salvo.target, salvo.shots)),
+        };
+        match fired {
+            Ok((target, positions)) => shots.extend(positions.into_iter().map(|pos| Shot {
+                target: target.clone(),
+                pos,
+            })),
+            Err(_) => return error_response(&ChainError::MalformedPayload),
+        }
+    }
+    Json(shots).into_response()
 }
 
 // -----------------------------------------------------------------------------
 // AUXILIARY FUNCTIONS
 // -----------------------------------------------------------------------------
 
-fn xy_pos(pos: Option<u8>) -> String {
+fn xy_pos(pos: Option<Position>, rules: &GameRules) -> String {
     match pos {
-        Some(p) => {
-            let x = p % 10;
-            let y = p / 10;
-            format!("{}{}", (x + 65) as char, y)
-        }
+        Some(p) => rules.label(p),
         None => "None".to_string(),
     }
 }
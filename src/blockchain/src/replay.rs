@@ -0,0 +1,65 @@
+//! Replay protection for signed chain transactions.
+//!
+//! Every signer must use strictly increasing nonces, and a receipt can only be
+//! consumed once. Both records are derived from the ledger, so they survive a
+//! restart without being persisted separately.
+
+use crate::ledger::Ledger;
+use fleetcore::{ChainError, CommunicationData, SignedMessage};
+use risc0_zkvm::{Digest, Receipt};
+use sha2::{Digest as ShaDigest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Default)]
+pub struct ReplayGuard {
+    nonces: HashMap<Vec<u8>, u64>, // Last nonce accepted per Dilithium public key
+    receipts: HashSet<Digest>,     // Digests of every consumed receipt
+}
+
+impl ReplayGuard {
+    /// Rebuilds the guard from every transaction recorded in the ledger.
+    pub fn from_ledger(ledger: &Ledger) -> Self {
+        let mut guard = ReplayGuard::default();
+        for block in ledger.blocks() {
+            for tx in &block.transactions {
+                guard.consume(&tx.signed);
+            }
+        }
+        guard
+    }
+
+    /// Checks that the message uses a fresh nonce and an unused receipt.
+    pub fn check(&self, signed: &SignedMessage<CommunicationData>) -> Result<(), ChainError> {
+        if let Some(&last) = self.nonces.get(&signed.public_key) {
+            if signed.nonce <= last {
+                return Err(ChainError::StaleNonce {
+                    last,
+                    got: signed.nonce,
+                });
+            }
+        }
+
+        let digest = receipt_digest(&signed.payload.receipt);
+        if self.receipts.contains(&digest) {
+            return Err(ChainError::ReusedReceipt {
+                digest: digest.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Marks the message's nonce and receipt as used.
+    pub fn consume(&mut self, signed: &SignedMessage<CommunicationData>) {
+        let last = self.nonces.entry(signed.public_key.clone()).or_default();
+        *last = (*last).max(signed.nonce);
+        self.receipts
+            .insert(receipt_digest(&signed.payload.receipt));
+    }
+}
+
+/// SHA-256 over the full serialized receipt, seal included.
+fn receipt_digest(receipt: &Receipt) -> Digest {
+    let bytes = serde_json::to_vec(receipt).expect("Receipt is serializable");
+    Digest::try_from(Sha256::digest(bytes).as_slice()).expect("Hash size mismatch")
+}
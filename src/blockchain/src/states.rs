@@ -1,37 +1,294 @@
+use crate::contract::ContractRegistry;
+use crate::events::EventBus;
+use crate::ledger::Ledger;
+use crate::replay::ReplayGuard;
+use crate::store::Store;
+use fleetcore::{
+    ChainError, Command, EncryptedToken, Fleet, FleetStatus, GameConfig, Position, Standing,
+};
 use risc0_zkvm::Digest;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
     collections::HashMap,
+    fmt,
     sync::{Arc, Mutex},
-    time::Instant,
+    time::{Duration, SystemTime},
 };
-use tokio::sync::broadcast;
 
 #[derive(Clone)]
 pub struct SharedData {
-    pub tx: broadcast::Sender<String>,
+    pub bus: Arc<EventBus>,
     pub gmap: Arc<Mutex<HashMap<String, Game>>>,
     pub rng: Arc<Mutex<rand::rngs::StdRng>>,
+    pub ledger: Arc<Mutex<Ledger>>,
+    pub store: Arc<Store>,
+    pub replay: Arc<Mutex<ReplayGuard>>,
+    pub contracts: Arc<ContractRegistry>,
+    pub config: ChainConfig,
+}
+
+impl SharedData {
+    /// A view of the chain holding only a copy of game `gameid`, if it exists,
+    /// and a bus holding announcements back. Transactions are applied to it, so
+    /// the live game is only replaced, and the change announced, once the
+    /// outcome has been written to the store.
+    pub fn staged(&self, gameid: &str) -> SharedData {
+        let game = self.gmap.lock().unwrap().get(gameid).cloned();
+        let scratch = game.map(|g| (gameid.to_string(), g)).into_iter().collect();
+        SharedData {
+            gmap: Arc::new(Mutex::new(scratch)),
+            bus: Arc::new(EventBus::staging()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Timing rules enforced by the chain, read from the environment at startup.
+#[derive(Clone)]
+pub struct ChainConfig {
+    pub contest_window: Duration, // How long a victory claim stays open to contest
+    pub turn_timeout: Duration,   // How long a player may hold the turn or owe a report
+    pub max_strikes: u32,         // Timeouts after which a fleet is forfeited
+    pub event_backlog: usize,     // Events kept per game for reconnecting clients
+}
+
+impl ChainConfig {
+    /// Reads `CONTEST_WINDOW_SECS`, `TURN_TIMEOUT_SECS`, `MAX_STRIKES` and
+    /// `EVENT_BACKLOG`, falling back to 60s, 120s, 3 strikes and 256 events.
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        ChainConfig {
+            contest_window: Duration::from_secs(var("CONTEST_WINDOW_SECS", 60)),
+            turn_timeout: Duration::from_secs(var("TURN_TIMEOUT_SECS", 120)),
+            max_strikes: var("MAX_STRIKES", 3),
+            event_backlog: var("EVENT_BACKLOG", 256),
+        }
+    }
 }
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Player {
     pub name: String,          // Player ID
     pub current_state: Digest, // Commitment hash
+    #[serde(default)]
+    pub joined_state: Digest, // Commitment the fleet joined with, where its history starts
     pub public_key: Vec<u8>,   // Dilithium public key
     pub rsa_pubkey: Vec<u8>,   // Token RSA public key
+    #[serde(default)]
+    pub strikes: u32, // Turn timeouts counted against this fleet
+    #[serde(default)]
+    pub forfeited: bool, // Removed from the rotation after too many strikes
+    #[serde(default)]
+    pub eliminated: bool, // Proved that their whole fleet was sunk
+    #[serde(default)]
+    pub shot_log: Digest, // Head of the hash chain of every shot this fleet fired
+    #[serde(default)]
+    pub shots_taken: u32, // Length of that chain
+}
+
+impl Player {
+    /// Whether the fleet can still be targeted and receive the turn.
+    pub fn is_active(&self) -> bool {
+        !self.forfeited && !self.eliminated
+    }
+}
+
+/// A fleet's board as opened after the game, with what the chain's audit found.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Reveal {
+    pub board: Fleet,            // Board the fleet joined with
+    pub random: String,          // Nonce of all its commitments
+    pub mismatches: Vec<String>, // Steps the opening does not reproduce; empty if honest
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PendingShot {
+    pub shooter: String, // Fleet that fired
+    pub target: String,  // Fleet that must report the shots
+    #[serde(alias = "pos", deserialize_with = "one_or_many")]
+    pub shots: Vec<Position>, // Targeted positions, a single one outside salvo games
+}
+
+/// Reads a list of positions, or the single position older logs recorded.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Position>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Position),
+        Many(Vec<Position>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(pos) => vec![pos],
+        OneOrMany::Many(shots) => shots,
+    })
 }
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct PendingWin {
     pub claimant: String, // Fleet ID that claimed win
     pub board: Digest,    // Committed board hash
-    pub time: Instant,    // Time when claim was made
+    pub time: SystemTime, // Time when claim was made
+    #[serde(default)]
+    pub standings: Vec<Standing>, // Standings the win proof committed
+}
+
+/// Lifecycle of a game. Which commands each phase accepts, and where they lead,
+/// is decided by [`GamePhase::transition`] alone.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum GamePhase {
+    #[default]
+    Lobby, // Players may join until the creator starts the game
+    InProgress,     // A player holds the turn
+    AwaitingReport, // A shot is in flight and the target must report it
+    WinClaimed,     // A victory claim is open to contest
+    Finished,       // The game is over
+}
+
+impl GamePhase {
+    /// Central transition table shared by every handler.
+    ///
+    /// # Returns
+    /// - `Ok(next)` with the phase the game enters once the command is applied
+    /// - `Err(ChainError::IllegalCommand)` if the command is illegal in the current phase
+    pub fn transition(self, cmd: Command) -> Result<GamePhase, ChainError> {
+        use Command::*;
+        use GamePhase::*;
+
+        match (self, cmd) {
+            (Lobby, Join) => Ok(Lobby),
+            (Lobby, Start) => Ok(InProgress),
+            (InProgress, Fire) => Ok(AwaitingReport),
+            (InProgress, Wave) => Ok(InProgress),
+            (AwaitingReport, Report) => Ok(InProgress),
+            (InProgress, Win) => Ok(WinClaimed),
+            (WinClaimed, Contest) => Ok(InProgress),
+            (InProgress, Defeat) => Ok(InProgress),
+            (Finished, Reveal) => Ok(Finished),
+            // The clock drops a shot in flight and hands the turn on
+            (InProgress | AwaitingReport, Timeout) => Ok(InProgress),
+            (InProgress | WinClaimed, Close) => Ok(Finished),
+            // Proving a fleet's history changes nothing, whatever the phase
+            (phase, History) => Ok(phase),
+            _ => Err(ChainError::IllegalCommand {
+                cmd,
+                phase: self.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GamePhase::Lobby => "in the lobby",
+            GamePhase::InProgress => "in progress",
+            GamePhase::AwaitingReport => "awaiting a shot report",
+            GamePhase::WinClaimed => "waiting on a victory claim",
+            GamePhase::Finished => "finished",
+        })
+    }
 }
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Game {
-    pub pmap: HashMap<String, Player>,   // All players in the game
-    pub shot_position: Option<u8>,       // Last shot position
+    #[serde(default)]
+    pub phase: GamePhase, // Current lifecycle phase
+    #[serde(default)]
+    pub creator: String, // Fleet that created the game and may start it
+    #[serde(default)]
+    pub config: GameConfig, // Player limits fixed at creation
+    #[serde(default)]
+    pub commitment_version: u16, // Board commitment scheme every proof must use
+    pub pmap: HashMap<String, Player>, // All players in the game
+    #[serde(default)]
+    pub order: Vec<String>, // Turn rotation, in join order until the game starts
+    #[serde(default)]
+    pub turn_seed: Digest, // Chain randomness the turn order is drawn from, kept secret until Start
+    #[serde(default)]
+    pub turn_seed_commitment: Digest, // Published when the game is created
+    pub pending_shot: Option<PendingShot>, // Shot waiting to be reported
     pub pending_win: Option<PendingWin>, // If someone has claimed victory
+    #[serde(default)]
+    pub winner: Option<String>, // Set once the game has finished
+    #[serde(default)]
+    pub reveals: HashMap<String, Reveal>, // Boards opened after the game, by fleet
 
     // Token authentication
     pub turn_commitment: Option<Digest>,
     pub encrypted_token: Option<String>,
+    #[serde(default)]
+    pub turn_holder: Option<String>, // Fleet the current token is encrypted to
+    #[serde(default)]
+    pub turn_deadline: Option<SystemTime>, // When the holder times out
+}
+
+impl Game {
+    /// Hands the turn to the recipient of `token` and restarts the turn clock.
+    pub fn pass_turn(&mut self, token: &EncryptedToken, timeout: Duration) {
+        self.encrypted_token = Some(token.enc_token.clone());
+        self.turn_commitment = Some(token.token_hash);
+        self.turn_holder = self.fleet_by_rsa_key(&token.pub_rsa_key);
+        self.turn_deadline = Some(SystemTime::now() + timeout);
+    }
+
+    /// Records the winner and takes the turn away from everyone. The phase is
+    /// left to the caller, as the game's contract decides it.
+    pub fn finish(&mut self, winner: Option<String>) {
+        self.winner = winner;
+        self.encrypted_token = None;
+        self.turn_commitment = None;
+        self.turn_holder = None;
+        self.turn_deadline = None;
+    }
+
+    /// Where every fleet stands, in turn order.
+    pub fn standings(&self) -> Vec<Standing> {
+        self.order
+            .iter()
+            .filter_map(|id| self.pmap.get(id))
+            .map(|p| Standing {
+                fleet: p.name.clone(),
+                status: if p.eliminated {
+                    FleetStatus::Defeated
+                } else if p.forfeited {
+                    FleetStatus::Forfeited
+                } else {
+                    FleetStatus::Afloat
+                },
+            })
+            .collect()
+    }
+
+    /// Finds the fleet registered with the given token RSA key.
+    pub fn fleet_by_rsa_key(&self, rsa_pubkey: &[u8]) -> Option<String> {
+        self.pmap
+            .values()
+            .find(|p| p.rsa_pubkey == rsa_pubkey)
+            .map(|p| p.name.clone())
+    }
+
+    /// Fleets still in the rotation, in turn order.
+    pub fn active_players(&self) -> Vec<String> {
+        self.order
+            .iter()
+            .filter(|id| self.pmap.get(*id).is_some_and(Player::is_active))
+            .cloned()
+            .collect()
+    }
+
+    /// The first active fleet after `fleet` in turn order, wrapping around.
+    pub fn next_player_after(&self, fleet: &str) -> Option<String> {
+        let start = self.order.iter().position(|id| id == fleet).unwrap_or(0);
+        (1..=self.order.len())
+            .map(|i| &self.order[(start + i) % self.order.len()])
+            .find(|id| *id != fleet && self.pmap.get(*id).is_some_and(Player::is_active))
+            .cloned()
+    }
 }
@@ -0,0 +1,227 @@
+//! Append-only write-ahead log that makes the chain state survive restarts.
+//!
+//! Every sealed block is written as one JSON line together with a snapshot of
+//! the game it touched. Replaying the log on startup rebuilds the ledger and
+//! the game map exactly as they were before the emulator went down.
+
+use crate::ledger::{Block, Ledger};
+use crate::states::Game;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const WAL_FILE: &str = "wal.jsonl";
+
+/// One log line: the state of a game after a change, along with the block that
+/// caused it. Changes made by the chain itself, such as finalizing a game, carry
+/// no block.
+#[derive(Serialize, Deserialize)]
+struct WalEntry<B, G> {
+    block: Option<B>,
+    gameid: String,
+    game: G,
+    #[serde(default)] // Not recorded by older logs
+    last_event: u64, // Id of the last event announcing the change
+}
+
+pub struct Store {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+/// State rebuilt from the log on startup.
+pub struct Recovered {
+    pub ledger: Ledger,
+    pub gmap: HashMap<String, Game>,
+    pub last_event: u64, // Event ids continue from here
+}
+
+impl Store {
+    /// Opens (or creates) the log in `dir` and replays it.
+    ///
+    /// A torn last record, left behind by a crash mid-write, is discarded and
+    /// the log truncated to the end of the last complete one. Corruption
+    /// anywhere else is reported as an error.
+    pub fn open(dir: &Path) -> io::Result<(Store, Recovered)> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(WAL_FILE);
+
+        let mut ledger = Ledger::new();
+        let mut gmap = HashMap::new();
+        let mut last_event = 0;
+        let mut valid_len = 0u64;
+
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(&path)?);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line)?;
+                if read == 0 {
+                    break;
+                }
+
+                // A record is complete once its newline is on disk. Only the
+                // last one may be torn, with or without its newline.
+                let complete = line.ends_with(b"\n");
+                match serde_json::from_slice::<WalEntry<Block, Game>>(&line) {
+                    Ok(entry) if complete => {
+                        if let Some(block) = entry.block {
+                            ledger.restore(block);
+                        }
+                        gmap.insert(entry.gameid, entry.game);
+                        last_event = last_event.max(entry.last_event);
+                        valid_len += read as u64;
+                    }
+                    Err(e) if complete && !reader.fill_buf()?.is_empty() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Corrupted entry in {}: {}", path.display(), e),
+                        ))
+                    }
+                    _ => {
+                        eprintln!("Discarding torn entry at the end of {}", path.display());
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Err(height) = ledger.verify() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Recovered ledger is inconsistent at block {}", height),
+            ));
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.set_len(valid_len)?;
+
+        let store = Store {
+            path,
+            file: Mutex::new(file),
+        };
+        let recovered = Recovered {
+            ledger,
+            gmap,
+            last_event,
+        };
+        Ok((store, recovered))
+    }
+
+    /// Durably appends the new state of a game and the block that produced it, if any,
+    /// along with the id of the last event announcing them. A failed append leaves
+    /// the log as it was.
+    pub fn append(
+        &self,
+        block: Option<&Block>,
+        gameid: &str,
+        game: &Game,
+        last_event: u64,
+    ) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&WalEntry {
+            block,
+            gameid: gameid.to_string(),
+            game,
+            last_event,
+        })?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        let len = file.metadata()?.len();
+        let written = file.write_all(&line).and_then(|_| file.sync_data());
+        if written.is_err() {
+            // Drop whatever part of the record made it, so the next one starts clean
+            let _ = file.set_len(len);
+        }
+        written
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn game() -> Game {
+        serde_json::from_str(r#"{"pmap": {}}"#).unwrap()
+    }
+
+    /// A log holding games `a` and `b`, followed by `tail` written verbatim.
+    fn log_with_tail(tail: &[u8]) -> (tempfile::TempDir, u64) {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, _) = Store::open(dir.path()).unwrap();
+        store.append(None, "a", &game(), 1).unwrap();
+        store.append(None, "b", &game(), 2).unwrap();
+        let len = fs::metadata(store.path()).unwrap().len();
+
+        let mut file = OpenOptions::new().append(true).open(store.path()).unwrap();
+        file.write_all(tail).unwrap();
+        (dir, len)
+    }
+
+    #[test]
+    fn torn_tail_with_newline_is_truncated() {
+        let (dir, len) = log_with_tail(b"{\"block\":null,\"gameid\":\"c\"\n");
+
+        let (store, recovered) = Store::open(dir.path()).unwrap();
+        assert!(recovered.gmap.contains_key("a") && recovered.gmap.contains_key("b"));
+        assert!(!recovered.gmap.contains_key("c"));
+        assert_eq!(recovered.last_event, 2);
+        assert_eq!(fs::metadata(store.path()).unwrap().len(), len);
+    }
+
+    #[test]
+    fn torn_tail_without_newline_is_truncated() {
+        let (dir, len) = log_with_tail(b"{\"block\":null,\"gam");
+
+        let (store, recovered) = Store::open(dir.path()).unwrap();
+        assert_eq!(recovered.gmap.len(), 2);
+        assert_eq!(fs::metadata(store.path()).unwrap().len(), len);
+
+        // The next record starts on a line of its own
+        store.append(None, "c", &game(), 3).unwrap();
+        drop(store);
+        let (_, recovered) = Store::open(dir.path()).unwrap();
+        assert_eq!(recovered.gmap.len(), 3);
+    }
+
+    #[test]
+    fn corruption_before_the_tail_is_an_error() {
+        let entry = WalEntry::<Block, Game> {
+            block: None,
+            gameid: "c".to_string(),
+            game: game(),
+            last_event: 3,
+        };
+        let mut tail = b"not json\n".to_vec();
+        tail.extend(serde_json::to_vec(&entry).unwrap());
+        tail.push(b'\n');
+        let (dir, _) = log_with_tail(&tail);
+
+        let err = Store::open(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tampered_block_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, recovered) = Store::open(dir.path()).unwrap();
+        let mut block = recovered.ledger.seal(Vec::new());
+        block.header.timestamp += 1; // Stale block hash
+        store.append(Some(&block), "a", &game(), 0).unwrap();
+        drop(store);
+
+        let err = Store::open(dir.path()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
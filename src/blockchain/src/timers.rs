@@ -0,0 +1,190 @@
+//! Background tasks that keep games moving: settling victory claims nobody
+//! contested in time, and taking the turn away from idle players.
+
+use crate::contract::contract_of;
+use crate::states::{Game, SharedData};
+use crate::tokens::issue_turn_token;
+use fleetcore::{ChainError, Command, GameEvent};
+use std::time::{Duration, SystemTime};
+
+/// How often games are checked against their deadlines.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Spawns the task that enforces contest windows and turn deadlines.
+pub fn spawn_timers(shared: SharedData) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let shared = shared.clone();
+            // Off the runtime, as the sweep may wait on a transaction's proof check
+            let sweep = tokio::task::spawn_blocking(move || {
+                // Taken like a transaction does, so a sweep never lands between a
+                // transaction being applied to its staged game and being recorded
+                let _replay = shared.replay.lock().unwrap();
+                finalize_expired_wins(&shared);
+                expire_idle_turns(&shared);
+            });
+            if let Err(e) = sweep.await {
+                eprintln!("Timer sweep failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Marks every game whose victory claim outlived the contest window as finished.
+fn finalize_expired_wins(shared: &SharedData) {
+    let now = SystemTime::now();
+    let mut gmap = shared.gmap.lock().unwrap();
+
+    for (gameid, game) in gmap.iter_mut() {
+        let expired = game.pending_win.as_ref().is_some_and(|pending| {
+            now.duration_since(pending.time)
+                .is_ok_and(|age| age >= shared.config.contest_window)
+        });
+        if !expired {
+            continue;
+        }
+
+        // The game's contract decides whether the claim may close it
+        let closed =
+            contract_of(shared, game).and_then(|c| c.transition(game.phase, Command::Close));
+        let phase = match closed {
+            Ok(phase) => phase,
+            Err(_) => continue,
+        };
+
+        let mut next = game.clone();
+        let claimant = next.pending_win.take().unwrap().claimant;
+        next.finish(Some(claimant.clone()));
+        next.phase = phase;
+
+        let finished = GameEvent::GameFinished {
+            winner: Some(claimant.clone()),
+        };
+        if persist(shared, gameid, game, next, vec![finished]).is_err() {
+            continue;
+        }
+
+        let msg = format!(
+            "\
+            \x20 Game {} is over!\n\
+            \x20 ▶ Nobody contested the claim within {} seconds.\n\
+            \x20 ▶ Winner: {}\n\n\n",
+            gameid,
+            shared.config.contest_window.as_secs(),
+            claimant
+        );
+        shared.bus.log(msg);
+    }
+}
+
+/// Strikes the fleet that let its turn or pending report time out and hands
+/// the turn to the next active fleet. Fleets reaching the strike limit are
+/// forfeited; when a single fleet remains in the rotation it wins the game.
+fn expire_idle_turns(shared: &SharedData) {
+    let now = SystemTime::now();
+    let mut gmap = shared.gmap.lock().unwrap();
+
+    for (gameid, game) in gmap.iter_mut() {
+        if !game.turn_deadline.is_some_and(|deadline| now >= deadline) {
+            continue;
+        }
+
+        // The game's contract decides which phases the clock may interrupt
+        let contract = match contract_of(shared, game) {
+            Ok(contract) => contract,
+            Err(_) => continue,
+        };
+        let phase = match contract.transition(game.phase, Command::Timeout) {
+            Ok(phase) => phase,
+            Err(_) => continue,
+        };
+
+        // While a shot is in flight the holder is its target; the shot is dropped
+        let mut next = game.clone();
+        let idle = next.turn_holder.clone();
+        next.pending_shot = None;
+        next.phase = phase;
+
+        let mut msg = format!("\x20 Turn timed out in game {}!\n", gameid);
+        if let Some(player) = idle.as_ref().and_then(|id| next.pmap.get_mut(id)) {
+            player.strikes += 1;
+            if player.strikes >= shared.config.max_strikes {
+                player.forfeited = true;
+                msg += &format!(
+                    "\x20 ▶ {} forfeited after {} strikes.\n",
+                    player.name, player.strikes
+                );
+            } else {
+                msg += &format!(
+                    "\x20 ▶ Strike {}/{} for {}.\n",
+                    player.strikes, shared.config.max_strikes, player.name
+                );
+            }
+        }
+
+        let active = next.active_players();
+        let event = if active.len() < 2 {
+            next.phase = match contract.transition(next.phase, Command::Close) {
+                Ok(phase) => phase,
+                Err(_) => continue,
+            };
+            next.finish(active.first().cloned());
+            msg += &match &next.winner {
+                Some(winner) => format!("\x20 ▶ {} is the last fleet standing and wins!\n", winner),
+                None => "\x20 ▶ No fleet is left in the rotation.\n".to_string(),
+            };
+            Some(GameEvent::GameFinished {
+                winner: next.winner.clone(),
+            })
+        } else {
+            let to = idle
+                .and_then(|id| next.next_player_after(&id))
+                .unwrap_or_else(|| active[0].clone());
+            let rsa_pubkey = next.pmap[&to].rsa_pubkey.clone();
+
+            match issue_turn_token(shared, &rsa_pubkey) {
+                Ok(token) => {
+                    next.pass_turn(&token, shared.config.turn_timeout);
+                    msg += &format!("\x20 ▶ Turn passed to {}.\n", to);
+                    Some(GameEvent::TurnPassed { to })
+                }
+                Err(e) => {
+                    // Retry once the next deadline passes
+                    eprintln!("Failed to issue a turn token in game {}: {}", gameid, e);
+                    next.turn_deadline = Some(now + shared.config.turn_timeout);
+                    None
+                }
+            }
+        };
+
+        if persist(shared, gameid, game, next, event.into_iter().collect()).is_err() {
+            continue;
+        }
+        shared.bus.log(msg + "\n\n");
+    }
+}
+
+/// Writes the next state of a game to the store and only then replaces the
+/// live one with it and publishes the events announcing it. On failure the
+/// game is left as it was, so the next sweep tries again.
+fn persist(
+    shared: &SharedData,
+    gameid: &str,
+    game: &mut Game,
+    next: Game,
+    events: Vec<GameEvent>,
+) -> Result<(), ChainError> {
+    // No transaction can publish in between while the sweep runs
+    let last_event = shared.bus.last_id() + events.len() as u64;
+    if let Err(e) = shared.store.append(None, gameid, &next, last_event) {
+        eprintln!("Failed to persist the state of game {}: {}", gameid, e);
+        return Err(ChainError::StorageFailure);
+    }
+    *game = next;
+    for event in events {
+        shared.bus.emit(gameid, event);
+    }
+    Ok(())
+}
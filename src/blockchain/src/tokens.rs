@@ -0,0 +1,34 @@
+//! Turn tokens issued by the chain itself, e.g. when an idle player loses the turn.
+//!
+//! Tokens are built exactly like the host builds them: 32 random bytes,
+//! committed through their SHA-256 hash and encrypted with PKCS#1 v1.5 to the
+//! recipient's registered RSA key, so the recipient can prove ownership as usual.
+
+use crate::states::SharedData;
+use base64::{engine::general_purpose, Engine as _};
+use fleetcore::EncryptedToken;
+use rand::RngCore;
+use risc0_zkvm::Digest;
+use rsa::{pkcs1v15::Pkcs1v15Encrypt, pkcs8::DecodePublicKey, RsaPublicKey};
+use sha2::{Digest as ShaDigest, Sha256};
+
+/// Generates a fresh turn token encrypted to the given PEM-encoded RSA public key.
+pub fn issue_turn_token(shared: &SharedData, rsa_pubkey: &[u8]) -> Result<EncryptedToken, String> {
+    let pem = std::str::from_utf8(rsa_pubkey).map_err(|_| "RSA key is not valid PEM")?;
+    let key = RsaPublicKey::from_public_key_pem(pem).map_err(|_| "Invalid RSA public key")?;
+
+    let mut rng = shared.rng.lock().unwrap();
+    let mut token = [0u8; 32];
+    rng.fill_bytes(&mut token);
+
+    let enc = key
+        .encrypt(&mut *rng, Pkcs1v15Encrypt, &token)
+        .map_err(|_| "Failed to encrypt turn token")?;
+    let hash = Digest::try_from(Sha256::digest(token).as_slice()).expect("Hash size mismatch");
+
+    Ok(EncryptedToken {
+        enc_token: general_purpose::STANDARD.encode(enc),
+        token_hash: hash,
+        pub_rsa_key: rsa_pubkey.to_vec(),
+    })
+}
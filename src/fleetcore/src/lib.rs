@@ -3,18 +3,21 @@
 
 use risc0_zkvm::{Digest, Receipt};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest as ShaDigest, Sha256};
 
 // -----------------------------------------------------------------------------
 // INPUT STRUCTS
 // -----------------------------------------------------------------------------
 
-/// Input to zkVM programs sent by the rust code for input on the methods join, wave and win
+/// Input to zkVM programs sent by the rust code for input on the methods join, start, wave and defeat
 /// The struct is read by the zkvm code and the data is used to generate the output Journal
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct BaseInputs {
     pub gameid: String,
     pub fleet: String,
-    pub board: Vec<u8>,
+    pub signer: Digest, // Hash of the Dilithium key that submits the proof
+    pub rules: GameRules,
+    pub board: Fleet,
     pub random: String,
     pub token_auth: Option<TokenAuth>,
 }
@@ -25,11 +28,91 @@ pub struct BaseInputs {
 pub struct FireInputs {
     pub gameid: String,
     pub fleet: String,
-    pub board: Vec<u8>,
+    pub signer: Digest, // Hash of the Dilithium key that submits the proof
+    pub rules: GameRules,
+    pub board: Fleet,
     pub random: String,
     pub target: String,
-    pub pos: u8,
+    pub pos: Position,
     pub token_auth: Option<TokenAuth>,
+    pub shot_log: Vec<Shot>, // Every shot fired so far by this fleet; unused by report
+}
+
+/// Input to the salvo and salvo report zkVM programs, used in [`GameMode::Salvo`] games.
+/// Salvo fire reads `target` as the fleet fired upon; the batched report ignores it.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SalvoInputs {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest, // Hash of the Dilithium key that submits the proof
+    pub rules: GameRules,
+    pub board: Fleet,
+    pub random: String,
+    pub target: String,
+    pub shots: Vec<Position>,
+    pub token_auth: Option<TokenAuth>,
+    pub shot_log: Vec<Shot>, // Every shot fired so far by this fleet; unused by salvo report
+}
+
+/// A shot as recorded in its shooter's log
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Shot {
+    pub target: String, // Fleet fired upon
+    pub pos: Position,
+}
+
+/// Image IDs a history proof trusts. They are committed in every [`HistoryJournal`],
+/// so a verifier only has to compare them with the ones it knows.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryImageIds {
+    pub history: Digest, // The history program itself
+    pub join: Digest,
+    pub report: Digest,
+    pub salvo_report: Digest,
+}
+
+/// One step of a fleet's history, given as the raw journals of the receipts
+/// that the history program verifies as assumptions.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum HistoryStep {
+    Join { journal: Vec<u8> }, // Starts from the fleet's join receipt
+    Report { previous: Vec<u8>, journal: Vec<u8> }, // Extends a history receipt by a report
+    SalvoReport { previous: Vec<u8>, journal: Vec<u8> }, // Same, by a salvo report
+}
+
+/// Input to the history zkVM program
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryInputs {
+    pub signer: Digest, // Hash of the Dilithium key that submits the proof
+    pub image_ids: HistoryImageIds,
+    pub step: HistoryStep,
+}
+
+/// Input to the win zkVM program: the claimant's board and the standings they claim
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct WinInputs {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest, // Hash of the Dilithium key that submits the proof
+    pub rules: GameRules,
+    pub board: Fleet,
+    pub random: String,
+    pub standings: Vec<Standing>,
+}
+
+/// Status of a fleet in the final standings of a game
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum FleetStatus {
+    Afloat,    // Still in the game
+    Defeated,  // Proved that its whole fleet was sunk
+    Forfeited, // Removed after too many turn timeouts
+}
+
+/// One fleet's entry in the standings, listed in turn order
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Standing {
+    pub fleet: String,
+    pub status: FleetStatus,
 }
 
 /// Struct that contains the necessary fields to prove token ownership
@@ -39,19 +122,231 @@ pub struct TokenAuth {
     pub expected_hash: Digest, // Hash previously committed
 }
 
+// -----------------------------------------------------------------------------
+// GAME RULES
+// -----------------------------------------------------------------------------
+
+/// Index of a cell on the board, `y * width + x`
+pub type Position = u16;
+
+/// Whether ships may touch each other. Ships never touch side by side, or they
+/// could not be told apart.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Adjacency {
+    #[default]
+    Corners, // Ships may touch diagonally
+    Separated, // Ships may not touch at all
+}
+
+/// A player's ships, each with the cells that are still afloat, listed in the
+/// order of [`GameRules::ships`]. The index of a ship is its identity.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Fleet {
+    pub ships: Vec<Vec<Position>>,
+}
+
+impl Fleet {
+    /// Whether every ship has been sunk.
+    pub fn is_sunk(&self) -> bool {
+        self.ships.iter().all(|ship| ship.is_empty())
+    }
+
+    /// Number of ships that still have a cell afloat.
+    pub fn afloat(&self) -> usize {
+        self.ships.iter().filter(|ship| !ship.is_empty()).count()
+    }
+
+    /// Every cell still afloat, ship by ship.
+    pub fn cells(&self) -> impl Iterator<Item = Position> + '_ {
+        self.ships.iter().flatten().copied()
+    }
+
+    /// Index of the ship covering `pos`, if any.
+    pub fn ship_at(&self, pos: Position) -> Option<usize> {
+        self.ships.iter().position(|ship| ship.contains(&pos))
+    }
+
+    /// Splits a set of cells into straight ships and matches them to the fleet
+    /// of `rules`: the n-th ship of a given length in `rules.ships` is the n-th
+    /// one found scanning the board row by row. Returns `None` if the cells do
+    /// not form exactly that fleet. Adjacency is not checked here.
+    pub fn from_cells(rules: &GameRules, cells: &[Position]) -> Option<Fleet> {
+        let mut cells: Vec<Position> = cells.to_vec();
+        cells.sort_unstable();
+        cells.dedup();
+        if cells.iter().any(|&pos| !rules.contains(pos)) {
+            return None;
+        }
+
+        // Follow every unvisited cell right, or else down, to the end of its ship
+        let mut visited = vec![false; cells.len()];
+        let mut found: Vec<Vec<Position>> = Vec::new();
+        for i in 0..cells.len() {
+            if visited[i] {
+                continue;
+            }
+            let (x, _) = rules.coordinates(cells[i]);
+            let step = if x + 1 < rules.width && cells.binary_search(&(cells[i] + 1)).is_ok() {
+                1
+            } else {
+                rules.width
+            };
+
+            let mut ship = Vec::new();
+            let mut pos = cells[i] as u32;
+            while let Ok(j) = cells.binary_search(&(pos as Position)) {
+                if visited[j] {
+                    break;
+                }
+                visited[j] = true;
+                ship.push(pos as Position);
+                pos += step as u32;
+                if pos >= rules.area() || (step == 1 && rules.coordinates(pos as Position).0 == 0) {
+                    break;
+                }
+            }
+            found.push(ship);
+        }
+
+        let mut ships = Vec::with_capacity(rules.ships.len());
+        for &length in &rules.ships {
+            let i = found.iter().position(|s| s.len() == length as usize)?;
+            ships.push(found.remove(i));
+        }
+        found.is_empty().then_some(Fleet { ships })
+    }
+}
+
+/// Name of the class of ships of a given length, as disclosed when one sinks.
+pub fn ship_class(length: u16) -> String {
+    match length {
+        1 => "Submarine".to_string(),
+        2 => "Cruiser".to_string(),
+        3 => "Destroyer".to_string(),
+        4 => "Battleship".to_string(),
+        5 => "Carrier".to_string(),
+        n => format!("{}-cell ship", n),
+    }
+}
+
+/// Board geometry and fleet composition, chosen by the game creator
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GameRules {
+    pub width: u16,           // Columns
+    pub height: u16,          // Rows
+    pub ships: Vec<u16>,      // Length of every ship in the fleet
+    pub adjacency: Adjacency, // How close ships may be placed
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            width: 10,
+            height: 10,
+            ships: vec![5, 4, 3, 2, 2, 1, 1],
+            adjacency: Adjacency::Corners,
+        }
+    }
+}
+
+/// Domain tag for the digest of a rule set.
+const RULES_TAG: &[u8] = b"IST-SCom/game-rules";
+
+impl GameRules {
+    /// Number of cells on the board.
+    pub fn area(&self) -> u32 {
+        self.width as u32 * self.height as u32
+    }
+
+    /// Number of cells covered by the whole fleet.
+    pub fn fleet_cells(&self) -> u32 {
+        self.ships.iter().map(|&s| s as u32).sum()
+    }
+
+    /// Whether `pos` lies on the board.
+    pub fn contains(&self, pos: Position) -> bool {
+        (pos as u32) < self.area()
+    }
+
+    /// Column and row of a position.
+    pub fn coordinates(&self, pos: Position) -> (u16, u16) {
+        (pos % self.width, pos / self.width)
+    }
+
+    /// Human-readable cell name: spreadsheet-style column letters followed by the row, e.g. `C7`.
+    pub fn label(&self, pos: Position) -> String {
+        let (x, y) = self.coordinates(pos);
+        let mut column = Vec::new();
+        let mut n = x as u32 + 1;
+        while n > 0 {
+            column.push(b'A' + ((n - 1) % 26) as u8);
+            n = (n - 1) / 26;
+        }
+        column.reverse();
+        format!("{}{}", String::from_utf8(column).unwrap(), y)
+    }
+
+    /// Checks that the rules describe a playable game.
+    pub fn check(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("the board needs at least one row and one column".to_string());
+        }
+        if self.area() > Position::MAX as u32 + 1 {
+            return Err(format!(
+                "at most {} cells are supported",
+                Position::MAX as u32 + 1
+            ));
+        }
+        if self.ships.is_empty() {
+            return Err("the fleet needs at least one ship".to_string());
+        }
+        let longest = self.width.max(self.height);
+        if self.ships.iter().any(|&s| s == 0 || s > longest) {
+            return Err(format!("ships must be 1 to {} cells long", longest));
+        }
+        if self.fleet_cells() > self.area() {
+            return Err("the fleet does not fit on the board".to_string());
+        }
+        Ok(())
+    }
+
+    /// Canonical commitment to the rules, carried in every journal:
+    /// `SHA256(tag || width || height || adjacency || ship count || ship lengths)`,
+    /// with every integer big-endian.
+    pub fn digest(&self) -> Digest {
+        let mut hasher = Sha256::new();
+        hasher.update(RULES_TAG);
+        hasher.update(self.width.to_be_bytes());
+        hasher.update(self.height.to_be_bytes());
+        hasher.update([self.adjacency as u8]);
+        hasher.update((self.ships.len() as u32).to_be_bytes());
+        for ship in &self.ships {
+            hasher.update(ship.to_be_bytes());
+        }
+        Digest::try_from(hasher.finalize().as_slice()).expect("Hash size mismatch")
+    }
+}
+
 // -----------------------------------------------------------------------------
 // NETWORK COMMUNICATION
 // -----------------------------------------------------------------------------
 
 /// Enum used to define the command that will be sent to the server by the host in the communication packet
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Command {
     Join,
+    Start,
     Fire,
     Report,
     Wave,
     Win,
     Contest,
+    Defeat,
+    History,
+    Reveal,
+    // Issued by the chain's own clock, never accepted from a fleet
+    Timeout, // A turn or report deadline passed
+    Close,   // The game ends without a transaction, e.g. an uncontested claim
 }
 
 /// Struct used to specify the packet sent from the client to the blockchain server
@@ -60,6 +355,49 @@ pub struct CommunicationData {
     pub cmd: Command,
     pub receipt: Receipt,
     pub token_data: Option<EncryptedToken>,
+    pub game_config: Option<GameConfig>, // Only read on the Join that creates a game
+    #[serde(default)]
+    pub recipient: Option<String>, // Only read on a Wave: the fleet the turn is handed to
+}
+
+/// How many shots a player fires per turn
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum GameMode {
+    #[default]
+    Classic, // One shot, answered by one report
+    Salvo, // One shot per ship still afloat, answered by a single batched report
+}
+
+/// Lobby settings chosen by the game creator and fixed for the whole game
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GameConfig {
+    pub min_players: usize, // Players needed before the game can start
+    pub max_players: usize, // Lobby closes to new joins once full
+    #[serde(default)]
+    pub rules: GameRules, // Board and fleet every player must follow
+    #[serde(default)]
+    pub mode: GameMode, // Shots fired per turn
+    #[serde(default = "default_contract")]
+    pub contract: String, // Name of the rule set the chain enforces
+}
+
+/// Rule set a game is played under unless its creator names another.
+pub const DEFAULT_CONTRACT: &str = "battleship";
+
+fn default_contract() -> String {
+    DEFAULT_CONTRACT.to_string()
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            min_players: 2,
+            max_players: 8,
+            rules: GameRules::default(),
+            mode: GameMode::Classic,
+            contract: default_contract(),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -70,9 +408,13 @@ pub struct EncryptedToken {
 }
 
 /// Wrapper for signed messages.
+///
+/// The signature covers the JSON encoding of `(nonce, payload)`. Nonces must be
+/// strictly increasing per public key, so a message cannot be posted twice.
 #[derive(Serialize, Deserialize)]
 pub struct SignedMessage<T> {
     pub payload: T,
+    pub nonce: u64,
     pub signature: Vec<u8>,
     pub public_key: Vec<u8>,
 }
@@ -81,24 +423,57 @@ pub struct SignedMessage<T> {
 // JOURNALS
 // -----------------------------------------------------------------------------
 
-/// Struct used to specify the  output journal for join, wave and win methods
+/// Leading fields shared by every journal, enough to route and authenticate a receipt
+/// without knowing which method produced it
+#[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct JournalHeader {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest, // Hash of the Dilithium key allowed to submit the receipt
+    pub commitment_version: u16, // Board commitment scheme used by the proof
+    pub rules: Digest,  // Digest of the rules the proof was checked against
+}
+
+/// Struct used to specify the  output journal for join, start, wave and defeat methods
 #[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
 pub struct BaseJournal {
     pub gameid: String,
     pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
     pub board: Digest,
     pub token_commitment: Digest,
 }
 
+/// Struct used to specify the output journal for win method
+#[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct WinJournal {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
+    pub board: Digest,
+    pub token_commitment: Digest,
+    pub standings: Vec<Standing>, // Every fleet but the claimant is out of the game
+}
+
 /// Struct used to specify the output journal for fire method
 #[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
 pub struct FireJournal {
     pub gameid: String,
     pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
     pub board: Digest,
     pub target: String,
-    pub pos: u8,
+    pub pos: Position,
     pub token_commitment: Digest,
+    pub shot_log: Digest,      // Shot log the shot extends
+    pub next_shot_log: Digest, // Shot log including the shot
+    pub shots_taken: u32,      // Length of the extended log
 }
 
 /// Struct used to specify the output journal for report method
@@ -106,9 +481,608 @@ pub struct FireJournal {
 pub struct ReportJournal {
     pub gameid: String,
     pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
     pub report: String,
-    pub pos: u8,
+    pub pos: Position,
+    pub board: Digest,
+    pub next_board: Digest,
+    pub token_commitment: Digest,
+}
+
+/// Struct used to specify the output journal for salvo method
+#[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct SalvoJournal {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
+    pub board: Digest,
+    pub target: String,
+    pub shots: Vec<Position>,
+    pub token_commitment: Digest,
+    pub shot_log: Digest,      // Shot log the salvo extends
+    pub next_shot_log: Digest, // Shot log including every shot of the salvo
+    pub shots_taken: u32,      // Length of the extended log
+}
+
+/// Struct used to specify the output journal for salvo report method.
+/// `reports[i]` is the outcome of `shots[i]`, as in [`ReportJournal::report`].
+#[derive(Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct SalvoReportJournal {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
+    pub reports: Vec<String>,
+    pub shots: Vec<Position>,
     pub board: Digest,
     pub next_board: Digest,
     pub token_commitment: Digest,
 }
+
+/// A classic report is a batch of one.
+impl From<ReportJournal> for SalvoReportJournal {
+    fn from(report: ReportJournal) -> Self {
+        SalvoReportJournal {
+            gameid: report.gameid,
+            fleet: report.fleet,
+            signer: report.signer,
+            commitment_version: report.commitment_version,
+            rules: report.rules,
+            reports: vec![report.report],
+            shots: vec![report.pos],
+            board: report.board,
+            next_board: report.next_board,
+            token_commitment: report.token_commitment,
+        }
+    }
+}
+
+/// Struct used to specify the output journal for history method: the fleet's
+/// current board descends from `joined` through `reports` verified reports.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct HistoryJournal {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
+    pub image_ids: HistoryImageIds,
+    pub joined: Digest, // Board committed when the fleet joined
+    pub board: Digest,  // Board after the last folded report
+    pub reports: u32,   // Reports folded so far
+}
+
+/// Struct used to specify the output journal for reveal method: the opening of
+/// the fleet's commitments, published once the game is over.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, Default)]
+pub struct RevealJournal {
+    pub gameid: String,
+    pub fleet: String,
+    pub signer: Digest,
+    pub commitment_version: u16,
+    pub rules: Digest,
+    pub board: Fleet,   // Board the fleet claims to have joined with
+    pub random: String, // Nonce of all its commitments
+}
+
+/// Version of the board commitment scheme implemented by [`commit_board`].
+pub const COMMITMENT_VERSION: u16 = 3;
+
+/// Domain tag that keeps board commitments apart from any other SHA-256 use.
+const COMMITMENT_TAG: &[u8] = b"IST-SCom/fleet-commitment";
+
+/// Computes the commitment to a board under scheme [`COMMITMENT_VERSION`]:
+/// `SHA256(tag || version || len(nonce) || nonce || ship count || ships)`, where
+/// each ship is its cell count followed by its sorted cells. The version, cell
+/// counts and positions are big-endian `u16`s, the nonce length and ship count
+/// big-endian `u64` and `u32`.
+///
+/// Ships keep their order, so the commitment binds which cell belongs to which
+/// ship; cells within a ship are sorted, so their order does not matter.
+/// Version 1 hashed single-byte positions and version 2 a flat list of cells;
+/// neither is produced any more.
+pub fn commit_board(board: &Fleet, nonce: &str) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(COMMITMENT_TAG);
+    hasher.update(COMMITMENT_VERSION.to_be_bytes());
+    hasher.update((nonce.len() as u64).to_be_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.update((board.ships.len() as u32).to_be_bytes());
+    for ship in &board.ships {
+        let mut cells = ship.clone();
+        cells.sort_unstable();
+        hasher.update((cells.len() as u16).to_be_bytes());
+        for pos in cells {
+            hasher.update(pos.to_be_bytes());
+        }
+    }
+    Digest::try_from(hasher.finalize().as_slice()).expect("Hash size mismatch")
+}
+
+/// Domain tag for the links of a shot log.
+const SHOT_LOG_TAG: &[u8] = b"IST-SCom/shot-log";
+
+/// Extends a shot-log commitment by one shot:
+/// `SHA256(tag || head || len(target) || target || pos)`, with the target length
+/// as a big-endian `u64` and the position as a big-endian `u16`.
+pub fn extend_shot_log(head: &Digest, shot: &Shot) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(SHOT_LOG_TAG);
+    hasher.update(head.as_bytes());
+    hasher.update((shot.target.len() as u64).to_be_bytes());
+    hasher.update(shot.target.as_bytes());
+    hasher.update(shot.pos.to_be_bytes());
+    Digest::try_from(hasher.finalize().as_slice()).expect("Hash size mismatch")
+}
+
+/// Commitment to a whole shot log. The empty log commits to the zero digest.
+pub fn commit_shot_log(log: &[Shot]) -> Digest {
+    log.iter()
+        .fold(Digest::ZERO, |head, shot| extend_shot_log(&head, shot))
+}
+
+/// Domain tags for the turn-order seed commitment and the per-fleet draw.
+const TURN_SEED_TAG: &[u8] = b"IST-SCom/turn-seed";
+const TURN_ORDER_TAG: &[u8] = b"IST-SCom/turn-order";
+
+/// Commitment the chain publishes to its turn-order seed when a game is
+/// created: `SHA256(tag || seed)`.
+pub fn commit_turn_seed(seed: &Digest) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(TURN_SEED_TAG);
+    hasher.update(seed.as_bytes());
+    Digest::try_from(hasher.finalize().as_slice()).expect("Hash size mismatch")
+}
+
+/// Turn order drawn from a revealed seed. Every fleet, given with the board
+/// commitment it joined with, draws `SHA256(tag || seed || len(fleet) || fleet || commitment)`,
+/// with the fleet length as a big-endian `u64`; fleets move in ascending order
+/// of their draws.
+pub fn turn_order(seed: &Digest, fleets: &[(String, Digest)]) -> Vec<String> {
+    let mut draws: Vec<([u8; 32], &String)> = fleets
+        .iter()
+        .map(|(fleet, commitment)| {
+            let mut hasher = Sha256::new();
+            hasher.update(TURN_ORDER_TAG);
+            hasher.update(seed.as_bytes());
+            hasher.update((fleet.len() as u64).to_be_bytes());
+            hasher.update(fleet.as_bytes());
+            hasher.update(commitment.as_bytes());
+            (hasher.finalize().into(), fleet)
+        })
+        .collect();
+    draws.sort();
+    draws.into_iter().map(|(_, fleet)| fleet.clone()).collect()
+}
+
+/// Hash of a Dilithium public key, as committed in the `signer` field of every journal.
+pub fn signer_digest(public_key: &[u8]) -> Digest {
+    Digest::try_from(Sha256::digest(public_key).as_slice()).expect("Hash size mismatch")
+}
+
+// -----------------------------------------------------------------------------
+// CHAIN ERRORS
+// -----------------------------------------------------------------------------
+
+/// Reason the chain rejected a transaction. Each variant maps to a stable
+/// error code and an HTTP status, so clients can branch on failures.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ChainError {
+    MalformedPayload,
+    InvalidSignature,
+    PublicKeyMismatch { fleet: String },
+    SignerMismatch,
+    CommitmentVersionMismatch { expected: u16, got: u16 },
+    StaleNonce { last: u64, got: u64 },
+    ReusedReceipt { digest: String },
+    InvalidReceipt { cmd: Command },
+    GameNotFound { gameid: String },
+    BlockNotFound { height: u64 },
+    TransactionNotFound { hash: String },
+    PlayerNotFound { fleet: String, gameid: String },
+    IllegalCommand { cmd: Command, phase: String },
+    ReservedCommand { cmd: Command },
+    InvalidPlayerLimits { min: usize, max: usize },
+    InvalidRules { reason: String },
+    UnknownContract { name: String },
+    RulesMismatch,
+    PositionOutOfBounds { pos: Position },
+    AlreadyJoined { fleet: String, gameid: String },
+    GameFull { gameid: String, max: usize },
+    NotCreator { creator: String },
+    NotEnoughPlayers { joined: usize, needed: usize },
+    NotYourTurn,
+    MissingToken,
+    InvalidTokenRecipient,
+    MissingRecipient,
+    SelfRecipient,
+    InactiveRecipient { fleet: String },
+    CommitmentMismatch,
+    TargetNotFound { fleet: String },
+    TargetForfeited { fleet: String },
+    TargetEliminated { fleet: String },
+    SelfTarget,
+    NotTarget { target: String },
+    PlayerEliminated { fleet: String },
+    ShotMismatch { reported: String, fired: String },
+    ModeMismatch { mode: GameMode },
+    UntrustedImage,
+    HistoryMismatch,
+    ReportCountMismatch { proven: u32, recorded: u32 },
+    NoHistoryProof { fleet: String },
+    AlreadyRevealed { fleet: String },
+    FleetShapeMismatch { ships: usize, expected: usize },
+    ShotLogMismatch,
+    OwnClaim,
+    InactiveChallenger { fleet: String },
+    ContestWindowClosed,
+    OpponentsRemaining { fleets: Vec<String> },
+    StandingsMismatch,
+    StorageFailure,
+}
+
+impl ChainError {
+    /// Stable machine-readable code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChainError::MalformedPayload => "MALFORMED_PAYLOAD",
+            ChainError::InvalidSignature => "INVALID_SIGNATURE",
+            ChainError::PublicKeyMismatch { .. } => "PUBLIC_KEY_MISMATCH",
+            ChainError::SignerMismatch => "SIGNER_MISMATCH",
+            ChainError::CommitmentVersionMismatch { .. } => "COMMITMENT_VERSION_MISMATCH",
+            ChainError::StaleNonce { .. } => "STALE_NONCE",
+            ChainError::ReusedReceipt { .. } => "REUSED_RECEIPT",
+            ChainError::InvalidReceipt { .. } => "INVALID_RECEIPT",
+            ChainError::GameNotFound { .. } => "GAME_NOT_FOUND",
+            ChainError::BlockNotFound { .. } => "BLOCK_NOT_FOUND",
+            ChainError::TransactionNotFound { .. } => "TRANSACTION_NOT_FOUND",
+            ChainError::PlayerNotFound { .. } => "PLAYER_NOT_FOUND",
+            ChainError::IllegalCommand { .. } => "ILLEGAL_COMMAND",
+            ChainError::ReservedCommand { .. } => "RESERVED_COMMAND",
+            ChainError::InvalidPlayerLimits { .. } => "INVALID_PLAYER_LIMITS",
+            ChainError::InvalidRules { .. } => "INVALID_RULES",
+            ChainError::UnknownContract { .. } => "UNKNOWN_CONTRACT",
+            ChainError::RulesMismatch => "RULES_MISMATCH",
+            ChainError::PositionOutOfBounds { .. } => "POSITION_OUT_OF_BOUNDS",
+            ChainError::AlreadyJoined { .. } => "ALREADY_JOINED",
+            ChainError::GameFull { .. } => "GAME_FULL",
+            ChainError::NotCreator { .. } => "NOT_CREATOR",
+            ChainError::NotEnoughPlayers { .. } => "NOT_ENOUGH_PLAYERS",
+            ChainError::NotYourTurn => "NOT_YOUR_TURN",
+            ChainError::MissingToken => "MISSING_TOKEN",
+            ChainError::InvalidTokenRecipient => "INVALID_TOKEN_RECIPIENT",
+            ChainError::MissingRecipient => "MISSING_RECIPIENT",
+            ChainError::SelfRecipient => "SELF_RECIPIENT",
+            ChainError::InactiveRecipient { .. } => "INACTIVE_RECIPIENT",
+            ChainError::CommitmentMismatch => "COMMITMENT_MISMATCH",
+            ChainError::TargetNotFound { .. } => "TARGET_NOT_FOUND",
+            ChainError::TargetForfeited { .. } => "TARGET_FORFEITED",
+            ChainError::TargetEliminated { .. } => "TARGET_ELIMINATED",
+            ChainError::SelfTarget => "SELF_TARGET",
+            ChainError::NotTarget { .. } => "NOT_TARGET",
+            ChainError::PlayerEliminated { .. } => "PLAYER_ELIMINATED",
+            ChainError::ShotMismatch { .. } => "SHOT_MISMATCH",
+            ChainError::ModeMismatch { .. } => "MODE_MISMATCH",
+            ChainError::UntrustedImage => "UNTRUSTED_IMAGE",
+            ChainError::HistoryMismatch => "HISTORY_MISMATCH",
+            ChainError::ReportCountMismatch { .. } => "REPORT_COUNT_MISMATCH",
+            ChainError::NoHistoryProof { .. } => "NO_HISTORY_PROOF",
+            ChainError::AlreadyRevealed { .. } => "ALREADY_REVEALED",
+            ChainError::FleetShapeMismatch { .. } => "FLEET_SHAPE_MISMATCH",
+            ChainError::ShotLogMismatch => "SHOT_LOG_MISMATCH",
+            ChainError::OwnClaim => "OWN_CLAIM",
+            ChainError::InactiveChallenger { .. } => "INACTIVE_CHALLENGER",
+            ChainError::ContestWindowClosed => "CONTEST_WINDOW_CLOSED",
+            ChainError::OpponentsRemaining { .. } => "OPPONENTS_REMAINING",
+            ChainError::StandingsMismatch => "STANDINGS_MISMATCH",
+            ChainError::StorageFailure => "STORAGE_FAILURE",
+        }
+    }
+
+    /// HTTP status code the chain answers with.
+    pub fn status(&self) -> u16 {
+        match self {
+            ChainError::MalformedPayload
+            | ChainError::InvalidPlayerLimits { .. }
+            | ChainError::InvalidRules { .. }
+            | ChainError::UnknownContract { .. }
+            | ChainError::ReservedCommand { .. }
+            | ChainError::MissingToken
+            | ChainError::MissingRecipient => 400,
+            ChainError::InvalidSignature => 401,
+            ChainError::PublicKeyMismatch { .. }
+            | ChainError::SignerMismatch
+            | ChainError::NotCreator { .. }
+            | ChainError::NotYourTurn
+            | ChainError::NotTarget { .. }
+            | ChainError::InvalidTokenRecipient
+            | ChainError::OwnClaim => 403,
+            ChainError::GameNotFound { .. }
+            | ChainError::BlockNotFound { .. }
+            | ChainError::TransactionNotFound { .. }
+            | ChainError::PlayerNotFound { .. }
+            | ChainError::TargetNotFound { .. }
+            | ChainError::NoHistoryProof { .. } => 404,
+            ChainError::StaleNonce { .. }
+            | ChainError::ReusedReceipt { .. }
+            | ChainError::IllegalCommand { .. }
+            | ChainError::AlreadyJoined { .. }
+            | ChainError::GameFull { .. }
+            | ChainError::NotEnoughPlayers { .. }
+            | ChainError::TargetForfeited { .. }
+            | ChainError::TargetEliminated { .. }
+            | ChainError::InactiveRecipient { .. }
+            | ChainError::PlayerEliminated { .. }
+            | ChainError::OpponentsRemaining { .. }
+            | ChainError::InactiveChallenger { .. }
+            | ChainError::ContestWindowClosed
+            | ChainError::AlreadyRevealed { .. }
+            | ChainError::ModeMismatch { .. } => 409,
+            ChainError::InvalidReceipt { .. }
+            | ChainError::CommitmentMismatch
+            | ChainError::CommitmentVersionMismatch { .. }
+            | ChainError::RulesMismatch
+            | ChainError::PositionOutOfBounds { .. }
+            | ChainError::SelfTarget
+            | ChainError::SelfRecipient
+            | ChainError::ShotMismatch { .. }
+            | ChainError::StandingsMismatch
+            | ChainError::UntrustedImage
+            | ChainError::HistoryMismatch
+            | ChainError::ReportCountMismatch { .. }
+            | ChainError::FleetShapeMismatch { .. }
+            | ChainError::ShotLogMismatch => 422,
+            ChainError::StorageFailure => 500,
+        }
+    }
+}
+
+impl core::fmt::Display for ChainError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ChainError::MalformedPayload => write!(f, "Malformed payload or journal"),
+            ChainError::InvalidSignature => write!(f, "Invalid signature"),
+            ChainError::PublicKeyMismatch { fleet } => {
+                write!(f, "Public key mismatch for player {}", fleet)
+            }
+            ChainError::SignerMismatch => {
+                write!(f, "Receipt was proven for a different signing key")
+            }
+            ChainError::CommitmentVersionMismatch { expected, got } => write!(
+                f,
+                "Board commitment scheme v{} does not match the game's scheme v{}",
+                got, expected
+            ),
+            ChainError::StaleNonce { last, got } => write!(
+                f,
+                "Replayed transaction: nonce {} is not greater than last used nonce {}",
+                got, last
+            ),
+            ChainError::ReusedReceipt { digest } => {
+                write!(
+                    f,
+                    "Replayed transaction: receipt {} was already used",
+                    digest
+                )
+            }
+            ChainError::InvalidReceipt { cmd } => {
+                write!(f, "Could not verify {:?} receipt", cmd)
+            }
+            ChainError::GameNotFound { gameid } => write!(f, "Game {} not found", gameid),
+            ChainError::BlockNotFound { height } => write!(f, "Block {} not found", height),
+            ChainError::TransactionNotFound { hash } => {
+                write!(f, "Transaction {} not found", hash)
+            }
+            ChainError::PlayerNotFound { fleet, gameid } => {
+                write!(f, "Player {} not found in game {}", fleet, gameid)
+            }
+            ChainError::ReservedCommand { cmd } => {
+                write!(f, "{:?} is issued by the chain itself", cmd)
+            }
+            ChainError::IllegalCommand { cmd, phase } => write!(
+                f,
+                "Command {:?} is not allowed while the game is {}",
+                cmd, phase
+            ),
+            ChainError::InvalidPlayerLimits { min, max } => write!(
+                f,
+                "Invalid player limits: at least {} and at most {} players",
+                min, max
+            ),
+            ChainError::InvalidRules { reason } => write!(f, "Invalid game rules: {}", reason),
+            ChainError::UnknownContract { name } => {
+                write!(f, "No game contract is registered as '{}'", name)
+            }
+            ChainError::RulesMismatch => {
+                write!(f, "Proof was generated for different game rules")
+            }
+            ChainError::PositionOutOfBounds { pos } => {
+                write!(f, "Position {} is outside the board", pos)
+            }
+            ChainError::AlreadyJoined { fleet, gameid } => {
+                write!(f, "Player {} is already in game {}", fleet, gameid)
+            }
+            ChainError::GameFull { gameid, max } => {
+                write!(f, "Game {} is full ({} players)", gameid, max)
+            }
+            ChainError::NotCreator { creator } => {
+                write!(f, "Only the game creator ({}) can start the game", creator)
+            }
+            ChainError::NotEnoughPlayers { joined, needed } => write!(
+                f,
+                "Not enough players to start: {} joined, {} needed",
+                joined, needed
+            ),
+            ChainError::NotYourTurn => write!(f, "Invalid token: not your turn"),
+            ChainError::MissingToken => write!(f, "Missing turn token"),
+            ChainError::InvalidTokenRecipient => {
+                write!(f, "Turn token is not encrypted to the expected player")
+            }
+            ChainError::MissingRecipient => write!(f, "Missing the fleet to hand the turn to"),
+            ChainError::SelfRecipient => write!(f, "A fleet cannot wave the turn to itself"),
+            ChainError::InactiveRecipient { fleet } => {
+                write!(
+                    f,
+                    "Fleet {} is out of the game and cannot take the turn",
+                    fleet
+                )
+            }
+            ChainError::CommitmentMismatch => {
+                write!(f, "Fleet commitment does not match recorded state")
+            }
+            ChainError::TargetNotFound { fleet } => write!(f, "Target {} does not exist", fleet),
+            ChainError::TargetForfeited { fleet } => {
+                write!(f, "Target {} has forfeited the game", fleet)
+            }
+            ChainError::TargetEliminated { fleet } => {
+                write!(f, "Target {} has already been defeated", fleet)
+            }
+            ChainError::SelfTarget => write!(f, "A fleet cannot fire at itself"),
+            ChainError::NotTarget { target } => {
+                write!(
+                    f,
+                    "Only the targeted fleet ({}) can report the shot",
+                    target
+                )
+            }
+            ChainError::PlayerEliminated { fleet } => {
+                write!(f, "Player {} has already been defeated", fleet)
+            }
+            ChainError::ShotMismatch { reported, fired } => write!(
+                f,
+                "Shot {} is not the shot fired by adversary ({})",
+                reported, fired
+            ),
+            ChainError::UntrustedImage => {
+                write!(f, "History proof relies on unknown program images")
+            }
+            ChainError::HistoryMismatch => write!(
+                f,
+                "History proof does not lead from the joined board to the current one"
+            ),
+            ChainError::ReportCountMismatch { proven, recorded } => write!(
+                f,
+                "History proof folds {} reports, the chain recorded {}",
+                proven, recorded
+            ),
+            ChainError::ShotLogMismatch => {
+                write!(
+                    f,
+                    "Shot log in the proof is not the one recorded for this fleet"
+                )
+            }
+            ChainError::NoHistoryProof { fleet } => {
+                write!(f, "No history proof was submitted for fleet {}", fleet)
+            }
+            ChainError::AlreadyRevealed { fleet } => {
+                write!(f, "Fleet {} has already revealed its board", fleet)
+            }
+            ChainError::FleetShapeMismatch { ships, expected } => write!(
+                f,
+                "Revealed board has {} ships, the game's fleet has {}",
+                ships, expected
+            ),
+            ChainError::ModeMismatch { mode } => {
+                write!(f, "This game is played in {:?} mode", mode)
+            }
+            ChainError::OwnClaim => write!(f, "You cannot contest your own victory"),
+            ChainError::InactiveChallenger { fleet } => {
+                write!(f, "Fleet {} is out of the game and cannot contest", fleet)
+            }
+            ChainError::ContestWindowClosed => {
+                write!(f, "The victory claim can no longer be contested")
+            }
+            ChainError::OpponentsRemaining { fleets } => write!(
+                f,
+                "Cannot claim victory while fleets are still afloat: [{}]",
+                fleets.join(", ")
+            ),
+            ChainError::StandingsMismatch => {
+                write!(f, "Claimed standings do not match the recorded game")
+            }
+            ChainError::StorageFailure => {
+                write!(f, "The chain could not persist the transaction")
+            }
+        }
+    }
+}
+
+/// JSON body the chain returns alongside a non-2xx status.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+    pub error: ChainError,
+}
+
+impl From<&ChainError> for ErrorResponse {
+    fn from(error: &ChainError) -> Self {
+        ErrorResponse {
+            code: error.code().to_string(),
+            message: error.to_string(),
+            error: error.clone(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// GAME EVENTS
+// -----------------------------------------------------------------------------
+
+/// State change in a game, streamed to clients as JSON tagged with its `type`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    PlayerJoined {
+        fleet: String,
+        players: usize,
+    },
+    ShotFired {
+        shooter: String,
+        target: String,
+        pos: Position,
+    },
+    SalvoFired {
+        shooter: String,
+        target: String,
+        shots: Vec<Position>,
+    },
+    ShotReported {
+        fleet: String,
+        pos: Position,
+        report: String,
+    },
+    ShipSunk {
+        fleet: String,
+        ship: String,
+    },
+    TurnPassed {
+        to: String,
+    },
+    TurnOrderFixed {
+        order: Vec<String>,
+        seed: Digest,
+    },
+    WinClaimed {
+        claimant: String,
+    },
+    WinContested {
+        claimant: String,
+        challenger: String,
+    },
+    PlayerDefeated {
+        fleet: String,
+    },
+    GameFinished {
+        winner: Option<String>,
+    },
+    BoardRevealed {
+        fleet: String,
+        honest: bool,
+    },
+}
@@ -1,16 +1,24 @@
 // src/game_actions.rs
 
 use crate::{
-    generate_receipt, send_receipt, unmarshal_data, unmarshal_fire, unmarshal_report, FormData,
+    build_fleet, generate_composed_receipt, generate_receipt, hit_cells, send_receipt, signer_of,
+    to_position, unmarshal_data, unmarshal_fire, unmarshal_report, unmarshal_rules,
+    unmarshal_salvo, FormData,
 };
 
-use fleetcore::{BaseInputs, Command, FireInputs, TokenAuth};
-use methods::{CONTEST_ELF, FIRE_ELF, JOIN_ELF, REPORT_ELF, WAVE_ELF, WIN_ELF};
+use fleetcore::{
+    BaseInputs, Command, FireInputs, GameConfig, GameMode, GameRules, HistoryImageIds,
+    HistoryInputs, HistoryStep, Position, SalvoInputs, Shot, Standing, TokenAuth, WinInputs,
+};
+use methods::{
+    CONTEST_ELF, DEFEAT_ELF, FIRE_ELF, HISTORY_ELF, HISTORY_ID, JOIN_ELF, JOIN_ID, REPORT_ELF,
+    REPORT_ID, REVEAL_ELF, SALVO_ELF, SALVO_REPORT_ELF, SALVO_REPORT_ID, START_ELF, WAVE_ELF,
+    WIN_ELF,
+};
 
 use risc0_zkvm::sha::Digest;
 use risc0_zkvm::{default_prover, ExecutorEnv, Receipt};
 
-use rand::{seq::IteratorRandom, SeedableRng};
 use reqwest::get;
 
 use base64::engine::general_purpose;
@@ -26,12 +34,27 @@ pub async fn join_game(idata: FormData) -> String {
         Err(err) => return err,
     };
 
+    // Existing games dictate the rules; a new game uses the ones in the form
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(_) => match unmarshal_rules(&idata) {
+            Ok(rules) => rules.unwrap_or_default(),
+            Err(err) => return err,
+        },
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
     // Create the zkVM input struct
     let input = BaseInputs {
-        gameid: gameid,
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
         token_auth: None,
     };
 
@@ -51,27 +74,88 @@ pub async fn join_game(idata: FormData) -> String {
     send_receipt(Command::Join, receipt, &idata, Some(rsa_pubkey)).await
 }
 
+pub async fn start(idata: FormData) -> String {
+    let (gameid, fleetid, board, random) = match unmarshal_data(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    let input = BaseInputs {
+        gameid,
+        fleet: fleetid,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        token_auth: None,
+    };
+
+    let receipt = match generate_receipt(&input, START_ELF) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    // The chain draws the turn order and issues the first token itself
+    send_receipt(Command::Start, receipt, &idata, None).await
+}
+
 pub async fn fire(idata: FormData) -> String {
+    // Salvo games fire a batch of shots with a proof of their own
+    if game_mode(&idata).await == Some(GameMode::Salvo) {
+        return salvo(idata).await;
+    }
+
     let (gameid, fleetid, board, random, targetfleet, x, y) = match unmarshal_fire(&idata) {
         Ok(values) => values,
         Err(err) => return err,
     };
 
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let pos = match to_position(&rules, x, y) {
+        Ok(pos) => pos,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    // The proof extends the log of every shot fired so far
+    let shot_log = match fetch_shot_log(&gameid, &fleetid).await {
+        Ok(shots) => shots,
+        Err(e) => return e,
+    };
+
     let gameid_clone = gameid.clone();
     let targetfleet_clone = targetfleet.clone();
 
     // Create the zkVM input struct
     let input = FireInputs {
-        gameid: gameid,
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
         target: targetfleet,
-        pos: y * 10 + x,
+        pos,
         token_auth: match build_token_auth(&gameid_clone, &idata).await {
             Ok(auth) => Some(auth),
             Err(e) => return e,
         },
+        shot_log,
     };
 
     // Generate Receipt
@@ -91,25 +175,46 @@ pub async fn fire(idata: FormData) -> String {
 }
 
 pub async fn report(idata: FormData) -> String {
+    // A salvo is answered with a single report covering every shot
+    if game_mode(&idata).await == Some(GameMode::Salvo) {
+        return salvo_report(idata).await;
+    }
+
     let (gameid, fleetid, board, random, report_value, x, y) = match unmarshal_report(&idata) {
         Ok(values) => values,
         Err(err) => return err,
     };
 
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let pos = match to_position(&rules, x, y) {
+        Ok(pos) => pos,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[pos]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
     let gameid_clone = gameid.clone();
 
     // Create the zkVM input struct
     let input = FireInputs {
-        gameid: gameid,
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
         target: report_value,
-        pos: y * 10 + x,
+        pos,
         token_auth: match build_token_auth(&gameid_clone, &idata).await {
             Ok(auth) => Some(auth),
             Err(e) => return e,
         },
+        shot_log: Vec::new(), // Only fire proofs extend the log
     };
 
     // Generate Receipt
@@ -128,20 +233,149 @@ pub async fn report(idata: FormData) -> String {
     send_receipt(Command::Report, receipt, &idata, Some(rsa_pubkey)).await
 }
 
+async fn salvo(idata: FormData) -> String {
+    let (gameid, fleetid, board, random, targetfleet, coordinates) = match unmarshal_salvo(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let shots = match coordinates
+        .iter()
+        .map(|&(x, y)| to_position(&rules, x, y))
+        .collect::<Result<Vec<Position>, String>>()
+    {
+        Ok(shots) => shots,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    // The proof extends the log of every shot fired so far
+    let shot_log = match fetch_shot_log(&gameid, &fleetid).await {
+        Ok(shots) => shots,
+        Err(e) => return e,
+    };
+
+    let gameid_clone = gameid.clone();
+    let targetfleet_clone = targetfleet.clone();
+
+    let input = SalvoInputs {
+        gameid,
+        fleet: fleetid,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        target: targetfleet,
+        shots,
+        token_auth: match build_token_auth(&gameid_clone, &idata).await {
+            Ok(auth) => Some(auth),
+            Err(e) => return e,
+        },
+        shot_log,
+    };
+
+    let receipt = match generate_receipt(&input, SALVO_ELF) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    // The target must answer the salvo, so it gets the turn token
+    let rsa_pubkey = match fetch_rsa_pubkey(&gameid_clone, &targetfleet_clone).await {
+        Ok(k) => k,
+        Err(e) => return e,
+    };
+
+    send_receipt(Command::Fire, receipt, &idata, Some(rsa_pubkey)).await
+}
+
+async fn salvo_report(idata: FormData) -> String {
+    let (gameid, fleetid, board, random) = match unmarshal_data(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    // The shots to answer are the ones the chain recorded; the proof works out each outcome
+    let game = match fetch_game(&gameid).await {
+        Ok(game) => game,
+        Err(e) => return e,
+    };
+    let shots = match game.pending_shot {
+        Some(pending) => pending.shots,
+        None => return "There is no salvo to report".to_string(),
+    };
+    let rules = game.config.rules;
+    let board = match build_fleet(&rules, &board, &idata, &shots) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    let gameid_clone = gameid.clone();
+
+    let input = SalvoInputs {
+        gameid,
+        fleet: fleetid,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        target: String::new(),
+        shots,
+        token_auth: match build_token_auth(&gameid_clone, &idata).await {
+            Ok(auth) => Some(auth),
+            Err(e) => return e,
+        },
+        shot_log: Vec::new(), // Only fire proofs extend the log
+    };
+
+    let receipt = match generate_receipt(&input, SALVO_REPORT_ELF) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    // Send your own pubkey
+    let rsa_pubkey = match &idata.rsa_pubkey {
+        Some(k) if !k.is_empty() => k.clone(),
+        _ => return "Missing RSA public key".to_string(),
+    };
+
+    send_receipt(Command::Report, receipt, &idata, Some(rsa_pubkey)).await
+}
+
 pub async fn wave(idata: FormData) -> String {
     let (gameid, fleetid, board, random) = match unmarshal_data(&idata) {
         Ok(values) => values,
         Err(err) => return err,
     };
+    let recipient = match idata.recipient.as_deref().map(str::trim) {
+        Some(r) if !r.is_empty() => r.to_string(),
+        _ => return "You must provide the Fleet ID to pass the turn to".to_string(),
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
 
     let gameid_clone = gameid.clone();
-    let fleetid_clone = fleetid.clone();
 
     let input = BaseInputs {
-        gameid: gameid,
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
         token_auth: match build_token_auth(&gameid_clone, &idata).await {
             Ok(auth) => Some(auth),
             Err(e) => return e,
@@ -153,12 +387,8 @@ pub async fn wave(idata: FormData) -> String {
         Err(e) => return format!("Proof generation failed, {e}"),
     };
 
-    let target = match pick_random_other_player(&gameid_clone, &fleetid_clone).await {
-        Some(id) => id,
-        None => return "No valid player to pass token to".to_string(),
-    };
-
-    let rsa_pubkey = match fetch_rsa_pubkey(&gameid_clone, &target).await {
+    // The token is encrypted to the fleet the turn is handed to
+    let rsa_pubkey = match fetch_rsa_pubkey(&gameid_clone, &recipient).await {
         Ok(k) => k,
         Err(e) => return e,
     };
@@ -172,12 +402,29 @@ pub async fn win(idata: FormData) -> String {
         Err(err) => return err,
     };
 
-    let input = BaseInputs {
-        gameid: gameid,
+    // The proof commits to the standings the chain has recorded
+    let standings = match fetch_standings(&gameid).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    let input = WinInputs {
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
-        token_auth: None,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        standings,
     };
 
     let receipt = match generate_receipt(&input, WIN_ELF) {
@@ -194,11 +441,22 @@ pub async fn contest(idata: FormData) -> String {
         Err(err) => return err,
     };
 
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
     let input = BaseInputs {
-        gameid: gameid,
+        gameid,
         fleet: fleetid,
-        board: board,
-        random: random,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
         token_auth: None,
     };
 
@@ -210,6 +468,178 @@ pub async fn contest(idata: FormData) -> String {
     send_receipt(Command::Contest, receipt, &idata, None).await
 }
 
+pub async fn defeat(idata: FormData) -> String {
+    let (gameid, fleetid, board, random) = match unmarshal_data(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &[]) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    let input = BaseInputs {
+        gameid,
+        fleet: fleetid,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        token_auth: None,
+    };
+
+    let receipt = match generate_receipt(&input, DEFEAT_ELF) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    send_receipt(Command::Defeat, receipt, &idata, None).await
+}
+
+/// Opens the fleet's board and nonce on the chain once the game is over.
+pub async fn reveal(idata: FormData) -> String {
+    let (gameid, fleetid, board, random) = match unmarshal_data(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let rules = match fetch_rules(&gameid).await {
+        Ok(rules) => rules,
+        Err(e) => return e,
+    };
+    // The board the fleet joined with: every hit cell counts as afloat again
+    let hits = match hit_cells(&idata) {
+        Ok(hits) => hits,
+        Err(e) => return e,
+    };
+    let board = match build_fleet(&rules, &board, &idata, &hits) {
+        Ok(board) => board,
+        Err(e) => return e,
+    };
+
+    let input = BaseInputs {
+        gameid,
+        fleet: fleetid,
+        signer: signer_of(&idata),
+        rules,
+        board,
+        random,
+        token_auth: None,
+    };
+
+    let receipt = match generate_receipt(&input, REVEAL_ELF) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    send_receipt(Command::Reveal, receipt, &idata, None).await
+}
+
+/// Folds the fleet's join and report receipts, as recorded on the chain, into a
+/// single receipt proving its whole board history, and publishes it.
+pub async fn history(idata: FormData) -> String {
+    let (gameid, fleetid, _, _) = match unmarshal_data(&idata) {
+        Ok(values) => values,
+        Err(err) => return err,
+    };
+
+    let receipts = match fetch_fleet_receipts(&gameid, &fleetid).await {
+        Ok(r) => r,
+        Err(e) => return e,
+    };
+    let salvo = game_mode(&idata).await == Some(GameMode::Salvo);
+
+    let image_ids = HistoryImageIds {
+        history: HISTORY_ID.into(),
+        join: JOIN_ID.into(),
+        report: REPORT_ID.into(),
+        salvo_report: SALVO_REPORT_ID.into(),
+    };
+    let signer = signer_of(&idata);
+
+    // Start from the join receipt, then fold in every report in ledger order
+    let mut receipts = receipts.into_iter();
+    let join = match receipts.next() {
+        Some(r) if r.cmd == Command::Join => r.receipt,
+        _ => return "Fleet has not joined this game".to_string(),
+    };
+    let input = HistoryInputs {
+        signer,
+        image_ids: image_ids.clone(),
+        step: HistoryStep::Join {
+            journal: join.journal.bytes.clone(),
+        },
+    };
+    let mut proof = match generate_composed_receipt(&input, HISTORY_ELF, vec![join]) {
+        Ok(r) => r,
+        Err(e) => return format!("Proof generation failed, {e}"),
+    };
+
+    for report in receipts {
+        let previous = proof.journal.bytes.clone();
+        let journal = report.receipt.journal.bytes.clone();
+        let step = if salvo {
+            HistoryStep::SalvoReport { previous, journal }
+        } else {
+            HistoryStep::Report { previous, journal }
+        };
+        let input = HistoryInputs {
+            signer,
+            image_ids: image_ids.clone(),
+            step,
+        };
+        proof = match generate_composed_receipt(&input, HISTORY_ELF, vec![proof, report.receipt]) {
+            Ok(r) => r,
+            Err(e) => return format!("Proof generation failed, {e}"),
+        };
+    }
+
+    send_receipt(Command::History, proof, &idata, None).await
+}
+
+#[derive(Deserialize)]
+struct FleetReceipt {
+    cmd: Command,
+    receipt: Receipt,
+}
+
+async fn fetch_fleet_receipts(gameid: &str, fleetid: &str) -> Result<Vec<FleetReceipt>, String> {
+    let url = format!(
+        "http://chain0:3001/games/{}/fleets/{}/receipts",
+        gameid, fleetid
+    );
+    let resp = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch receipts".to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("Game {} does not exist", gameid));
+    }
+    resp.json()
+        .await
+        .map_err(|_| "Invalid receipt data".to_string())
+}
+
+async fn fetch_shot_log(gameid: &str, fleetid: &str) -> Result<Vec<Shot>, String> {
+    let url = format!(
+        "http://chain0:3001/games/{}/fleets/{}/shots",
+        gameid, fleetid
+    );
+    let resp = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch shot log".to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("Game {} does not exist", gameid));
+    }
+    resp.json()
+        .await
+        .map_err(|_| "Invalid shot log data".to_string())
+}
+
 #[derive(Deserialize)]
 struct TokenData {
     enc_token: String,
@@ -264,15 +694,57 @@ async fn fetch_rsa_pubkey(gameid: &str, fleetid: &str) -> Result<String, String>
     resp.text().await.map_err(|_| "Invalid RSA key".to_string())
 }
 
-pub async fn pick_random_other_player(gameid: &str, self_id: &str) -> Option<String> {
-    let url = format!("http://chain0:3001/players?gameid={}", gameid);
+/// The parts of the chain's game view the host needs.
+#[derive(Deserialize)]
+struct GameInfo {
+    config: GameConfig,
+    pending_shot: Option<PendingShotInfo>,
+}
 
-    let response = reqwest::get(&url).await.ok()?;
-    let players: Vec<String> = response.json().await.ok()?;
+#[derive(Deserialize)]
+struct PendingShotInfo {
+    shots: Vec<Position>,
+}
+
+async fn fetch_game(gameid: &str) -> Result<GameInfo, String> {
+    let url = format!("http://chain0:3001/games/{}", gameid);
+    let resp = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch game".to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("Game {} does not exist", gameid));
+    }
+    resp.json()
+        .await
+        .map_err(|_| "Invalid game data".to_string())
+}
+
+/// Mode of the game named in the form, if the chain knows it.
+async fn game_mode(idata: &FormData) -> Option<GameMode> {
+    let gameid = idata.gameid.as_deref().filter(|id| !id.is_empty())?;
+    fetch_game(gameid).await.ok().map(|game| game.config.mode)
+}
 
-    let mut rng = rand::rngs::StdRng::from_entropy();
-    players
-        .into_iter()
-        .filter(|id| id != self_id)
-        .choose(&mut rng)
+/// Rules of an existing game, as recorded by the chain.
+pub async fn fetch_rules(gameid: &str) -> Result<GameRules, String> {
+    let url = format!("http://chain0:3001/games/{}/rules", gameid);
+    let resp = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch game rules".to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("Game {} does not exist", gameid));
+    }
+    resp.json()
+        .await
+        .map_err(|_| "Invalid game rules data".to_string())
+}
+
+async fn fetch_standings(gameid: &str) -> Result<Vec<Standing>, String> {
+    let url = format!("http://chain0:3001/games/{}/standings", gameid);
+    let resp = reqwest::get(&url)
+        .await
+        .map_err(|_| "Failed to fetch standings".to_string())?;
+    resp.json()
+        .await
+        .map_err(|_| "Invalid standings data".to_string())
 }
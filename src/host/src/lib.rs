@@ -3,13 +3,17 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 
-use percent_encoding;
 use serde::{Deserialize, Serialize};
 
-use fleetcore::{Command, CommunicationData, EncryptedToken, SignedMessage};
+use fleetcore::{
+    signer_digest, Adjacency, Command, CommunicationData, EncryptedToken, ErrorResponse, Fleet,
+    GameConfig, GameMode, GameRules, Position, SignedMessage,
+};
 
 mod game_actions;
-pub use game_actions::{contest, fire, join_game, report, wave, win};
+pub use game_actions::{
+    contest, defeat, fetch_rules, fire, history, join_game, report, reveal, start, wave, win,
+};
 
 mod signing;
 use signing::{import_key_base64, sign_payload};
@@ -17,7 +21,7 @@ use signing::{import_key_base64, sign_payload};
 mod token_gen;
 use token_gen::prepare_turn_token;
 
-use risc0_zkvm::{default_prover, ExecutorEnv, Receipt};
+use risc0_zkvm::{default_prover, Digest, ExecutorEnv, ProverOpts, Receipt};
 use std::{error::Error, string};
 
 use reqwest::Client;
@@ -38,6 +42,19 @@ pub struct FormData {
     pub gameid: Option<String>,
     pub fleetid: Option<String>,
     pub targetfleet: Option<String>,
+    pub recipient: Option<String>, // Fleet a waved turn is handed to
+
+    // Lobby limits, only used when creating a game
+    pub min_players: Option<String>,
+    pub max_players: Option<String>,
+    pub mode: Option<String>,
+    pub contract: Option<String>, // Name of a rule set registered on the chain
+
+    // Board and fleet rules, only used when creating a game
+    pub width: Option<String>,
+    pub height: Option<String>,
+    pub ships: Option<String>,
+    pub adjacency: Option<String>,
 
     pub x: Option<String>,
     pub y: Option<String>,
@@ -66,6 +83,35 @@ fn generate_receipt<T: serde::Serialize>(input: &T, elf: &[u8]) -> Result<Receip
     Ok(session.receipt)
 }
 
+/// Like [`generate_receipt`], for programs that verify other receipts: those are
+/// compressed and supplied as assumptions, and the result is a succinct receipt.
+fn generate_composed_receipt<T: serde::Serialize>(
+    input: &T,
+    elf: &[u8],
+    assumptions: Vec<Receipt>,
+) -> Result<Receipt, String> {
+    let prover = default_prover();
+
+    let mut builder = ExecutorEnv::builder();
+    for receipt in assumptions {
+        let succinct = prover
+            .compress(&ProverOpts::succinct(), &receipt)
+            .map_err(|e| format!("receipt compression failed, {:?}", e))?;
+        builder.add_assumption(succinct);
+    }
+    let env = builder
+        .write(input)
+        .map_err(|e| format!("env write error: {:?}", e))?
+        .build()
+        .map_err(|e| format!("env build error: {:?}", e))?;
+
+    let session = prover
+        .prove_with_opts(env, elf, &ProverOpts::succinct())
+        .map_err(|e| format!("zkVM proof failed, {:?}", e))?;
+
+    Ok(session.receipt)
+}
+
 /// Sends a signed CommunicationData payload to the blockchain, optionally encrypting a turn token.
 pub async fn send_receipt(
     action: Command,
@@ -93,17 +139,34 @@ pub async fn send_receipt(
     // Construct payload
     let token_data = match (enc_token_opt, token_hash_opt) {
         (Some(enc_token), Some(token_hash)) => Some(EncryptedToken {
-            enc_token: enc_token,
+            enc_token,
             token_hash: token_hash.into(),
             pub_rsa_key: recipient_pubkey_bytes,
         }),
         _ => None,
     };
 
+    // Player limits only matter for the Join that creates the game
+    let game_config = match action {
+        Command::Join => match unmarshal_game_config(idata) {
+            Ok(config) => config,
+            Err(err) => return err,
+        },
+        _ => None,
+    };
+
+    // Only a Wave names the fleet it hands the turn to
+    let recipient = match action {
+        Command::Wave => idata.recipient.as_ref().map(|r| r.trim().to_string()),
+        _ => None,
+    };
+
     let payload = CommunicationData {
         cmd: action,
         receipt,
         token_data,
+        game_config,
+        recipient,
     };
 
     // Retrieve submitter's keys and sign payload
@@ -123,15 +186,27 @@ pub async fn send_receipt(
         .send()
         .await
     {
-        Ok(resp) => resp
+        Ok(resp) if resp.status().is_success() => resp
             .text()
             .await
             .unwrap_or_else(|_| "Failed to read response".to_string()),
+        // Rejections carry a structured error; show its message and stable code
+        Ok(resp) => match resp.json::<ErrorResponse>().await {
+            Ok(err) => format!("{} [{}]", err.message, err.code),
+            Err(_) => "Failed to read response".to_string(),
+        },
         Err(_) => "Error sending signed message".to_string(),
     }
 }
 
-pub fn unmarshal_data(idata: &FormData) -> Result<(String, String, Vec<u8>, String), String> {
+/// Hash of the submitter's Dilithium public key, bound into every proof.
+pub fn signer_of(idata: &FormData) -> Digest {
+    signer_digest(&import_key_base64(
+        idata.d_pubkey.as_deref().unwrap_or_default(),
+    ))
+}
+
+pub fn unmarshal_data(idata: &FormData) -> Result<(String, String, Vec<Position>, String), String> {
     let gameid = idata
         .gameid
         .clone()
@@ -172,54 +247,199 @@ pub fn unmarshal_data(idata: &FormData) -> Result<(String, String, Vec<u8>, Stri
                 .map(|decoded| {
                     decoded
                         .split(',')
+                        .filter(|s| !s.is_empty())
                         .map(|s| {
-                            s.parse::<u8>()
+                            s.parse::<Position>()
                                 .map_err(|_| "Invalid number in Board Placement".to_string())
                         })
-                        .collect::<Result<Vec<u8>, String>>()
+                        .collect::<Result<Vec<Position>, String>>()
                 })
         })??;
 
     Ok((gameid, fleetid, board, random))
 }
 
-fn get_coordinates(x: &Option<String>, y: &Option<String>) -> Result<(u8, u8), String> {
-    let x: u8 = x
-        .as_ref()
+/// Rebuilds the fleet, ship by ship, from the grid: `board` holds the cells still
+/// afloat and the form's shots the cells already hit. Hits being reported
+/// (`pending`) still count as afloat, as the proof starts from the committed board.
+pub fn build_fleet(
+    rules: &GameRules,
+    board: &[Position],
+    idata: &FormData,
+    pending: &[Position],
+) -> Result<Fleet, String> {
+    let hits = hit_cells(idata)?;
+
+    let cells: Vec<Position> = board.iter().chain(&hits).copied().collect();
+    let mut fleet = Fleet::from_cells(rules, &cells).ok_or_else(|| {
+        format!(
+            "Black and red cells must form a fleet of ships of lengths {:?}",
+            rules.ships
+        )
+    })?;
+    for ship in &mut fleet.ships {
+        ship.retain(|pos| pending.contains(pos) || !hits.contains(pos));
+    }
+
+    Ok(fleet)
+}
+
+/// Cells of the grid already hit, marked red on the board.
+pub fn hit_cells(idata: &FormData) -> Result<Vec<Position>, String> {
+    percent_encoding::percent_decode_str(idata.shots.as_deref().unwrap_or_default())
+        .decode_utf8()
+        .map_err(|_| "Invalid hit cells".to_string())?
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<Position>()
+                .map_err(|_| "Invalid number in hit cells".to_string())
+        })
+        .collect()
+}
+
+/// Reads the optional player limits, mode, rules and contract; a blank field falls back to the default.
+pub fn unmarshal_game_config(idata: &FormData) -> Result<Option<GameConfig>, String> {
+    let parse = |field: &Option<String>, name: &str| -> Result<Option<usize>, String> {
+        match field.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(v) => v
+                .parse::<usize>()
+                .map(Some)
+                .map_err(|_| format!("Invalid {} player count", name)),
+        }
+    };
+
+    let min = parse(&idata.min_players, "minimum")?;
+    let max = parse(&idata.max_players, "maximum")?;
+    let mode = match idata.mode.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some("Classic") => Some(GameMode::Classic),
+        Some("Salvo") => Some(GameMode::Salvo),
+        Some(_) => return Err("Mode must be either 'Classic' or 'Salvo'".to_string()),
+    };
+    let rules = unmarshal_rules(idata)?;
+    let contract = match idata.contract.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(name) => Some(name.to_string()),
+    };
+    if min.is_none() && max.is_none() && mode.is_none() && rules.is_none() && contract.is_none() {
+        return Ok(None);
+    }
+
+    let default = GameConfig::default();
+    Ok(Some(GameConfig {
+        min_players: min.unwrap_or(default.min_players),
+        max_players: max.unwrap_or(default.max_players),
+        rules: rules.unwrap_or(default.rules),
+        mode: mode.unwrap_or(default.mode),
+        contract: contract.unwrap_or(default.contract),
+    }))
+}
+
+/// Reads the optional board size, fleet and adjacency rule; blank fields fall back to the classic game.
+pub fn unmarshal_rules(idata: &FormData) -> Result<Option<GameRules>, String> {
+    let field = |f: &Option<String>| match f.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(v) => Some(v.to_string()),
+    };
+    let parse = |v: &str, name: &str| {
+        v.parse::<u16>()
+            .map_err(|_| format!("Invalid board {}", name))
+    };
+
+    let width = field(&idata.width);
+    let height = field(&idata.height);
+    let ships = field(&idata.ships);
+    let adjacency = field(&idata.adjacency);
+    if width.is_none() && height.is_none() && ships.is_none() && adjacency.is_none() {
+        return Ok(None);
+    }
+
+    let mut rules = GameRules::default();
+    if let Some(w) = width {
+        rules.width = parse(&w, "width")?;
+    }
+    if let Some(h) = height {
+        rules.height = parse(&h, "height")?;
+    }
+    if let Some(list) = ships {
+        rules.ships = list
+            .split(',')
+            .map(|s| {
+                s.trim()
+                    .parse::<u16>()
+                    .map_err(|_| "Invalid ship length in fleet".to_string())
+            })
+            .collect::<Result<Vec<u16>, String>>()?;
+    }
+    if let Some(a) = adjacency {
+        rules.adjacency = match a.as_str() {
+            "Corners" => Adjacency::Corners,
+            "Separated" => Adjacency::Separated,
+            _ => return Err("Adjacency must be either 'Corners' or 'Separated'".to_string()),
+        };
+    }
+    rules.check()?;
+
+    Ok(Some(rules))
+}
+
+/// Reads a column (letters such as `C` or `AB`, or a 0-based number) and a row number.
+/// Bounds are checked against the game's rules by [`to_position`].
+fn get_coordinates(x: &Option<String>, y: &Option<String>) -> Result<(u16, u16), String> {
+    let x: u16 = x
+        .as_deref()
+        .map(str::trim)
         .ok_or_else(|| "You must provide an X coordinate".to_string())
         .and_then(|id| {
-            if let Some(first_char) = id.chars().next() {
-                if ('A'..='J').contains(&first_char) {
-                    Ok(first_char as u8 - b'A')
-                } else {
-                    Err("X coordinate must be between A and J".to_string())
-                }
-            } else {
+            if id.is_empty() {
                 Err("Invalid X coordinate".to_string())
+            } else if id.chars().all(|c| c.is_ascii_digit()) {
+                id.parse::<u16>()
+                    .map_err(|_| "Invalid X coordinate".to_string())
+            } else if id.chars().all(|c| c.is_ascii_alphabetic()) {
+                // Spreadsheet-style columns: A..Z, AA..AZ, ...
+                id.to_ascii_uppercase()
+                    .bytes()
+                    .try_fold(0u32, |acc, c| {
+                        let n = acc * 26 + (c - b'A') as u32 + 1;
+                        (n <= u16::MAX as u32).then_some(n)
+                    })
+                    .map(|n| (n - 1) as u16)
+                    .ok_or_else(|| "X coordinate is too large".to_string())
+            } else {
+                Err("X coordinate must be a column letter or number".to_string())
             }
         })?;
 
-    let y: u8 = y
-        .as_ref()
+    let y: u16 = y
+        .as_deref()
+        .map(str::trim)
         .ok_or_else(|| "You must provide a Y coordinate".to_string())
         .and_then(|id| {
-            if let Some(first_char) = id.chars().next() {
-                if ('0'..='9').contains(&first_char) {
-                    Ok(first_char as u8 - b'0')
-                } else {
-                    Err("Y coordinate must be between 0 and 9".to_string())
-                }
-            } else {
-                Err("Invalid Y coordinate".to_string())
-            }
+            id.parse::<u16>()
+                .map_err(|_| "Y coordinate must be a row number".to_string())
         })?;
 
     Ok((x, y))
 }
 
-pub fn unmarshal_fire(
-    idata: &FormData,
-) -> Result<(String, String, Vec<u8>, String, String, u8, u8), String> {
+/// Turns a shot into a board position, rejecting shots off the board.
+pub fn to_position(rules: &GameRules, x: u16, y: u16) -> Result<Position, String> {
+    if x >= rules.width || y >= rules.height {
+        return Err(format!(
+            "Shot is off the {}x{} board",
+            rules.width, rules.height
+        ));
+    }
+    Ok(y * rules.width + x)
+}
+
+/// Game ID, Fleet ID, board, random nonce, target fleet or report value, and the (x, y) shot.
+pub type ShotData = (String, String, Vec<Position>, String, String, u16, u16);
+
+pub fn unmarshal_fire(idata: &FormData) -> Result<ShotData, String> {
     let (gameid, fleetid, board, random) = unmarshal_data(idata)?;
     let (x, y) = get_coordinates(&idata.x, &idata.y)?;
     let targetfleet = idata
@@ -230,9 +450,38 @@ pub fn unmarshal_fire(
     Ok((gameid, fleetid, board, random, targetfleet, x, y))
 }
 
-pub fn unmarshal_report(
-    idata: &FormData,
-) -> Result<(String, String, Vec<u8>, String, String, u8, u8), String> {
+/// Game ID, Fleet ID, board, random nonce, target fleet, and the (x, y) of every shot.
+pub type SalvoData = (
+    String,
+    String,
+    Vec<Position>,
+    String,
+    String,
+    Vec<(u16, u16)>,
+);
+
+/// Reads a salvo: comma-separated X and Y coordinates, paired in order.
+pub fn unmarshal_salvo(idata: &FormData) -> Result<SalvoData, String> {
+    let (gameid, fleetid, board, random) = unmarshal_data(idata)?;
+    let xs: Vec<&str> = idata.x.as_deref().unwrap_or_default().split(',').collect();
+    let ys: Vec<&str> = idata.y.as_deref().unwrap_or_default().split(',').collect();
+    if xs.len() != ys.len() {
+        return Err("Give as many X as Y coordinates, separated by commas".to_string());
+    }
+    let shots = xs
+        .iter()
+        .zip(&ys)
+        .map(|(x, y)| get_coordinates(&Some(x.to_string()), &Some(y.to_string())))
+        .collect::<Result<Vec<(u16, u16)>, String>>()?;
+    let targetfleet = idata
+        .targetfleet
+        .clone()
+        .ok_or_else(|| "You must provide a Target Fleet ID".to_string())?;
+
+    Ok((gameid, fleetid, board, random, targetfleet, shots))
+}
+
+pub fn unmarshal_report(idata: &FormData) -> Result<ShotData, String> {
     let (gameid, fleetid, board, random) = unmarshal_data(idata)?;
     let (x, y) = get_coordinates(&idata.rx, &idata.ry)?;
     let report = idata
@@ -240,10 +489,10 @@ pub fn unmarshal_report(
         .clone()
         .ok_or_else(|| "You must provide a Report value".to_string())
         .and_then(|r| {
-            if r == "Hit" || r == "Miss" {
+            if r == "Hit" || r == "Sunk" || r == "Miss" {
                 Ok(r)
             } else {
-                Err("Report must be either 'Hit' or 'Miss'".to_string())
+                Err("Report must be either 'Hit', 'Sunk' or 'Miss'".to_string())
             }
         })?;
 
@@ -13,7 +13,11 @@ use nanoid::nanoid;
 use serde_json::json;
 use tokio::signal;
 
-use host::{contest, fire, join_game, report, wave, win, FormData};
+use fleetcore::GameRules;
+use host::{
+    contest, defeat, fetch_rules, fire, history, join_game, report, reveal, start, unmarshal_rules,
+    wave, win, FormData,
+};
 use std::net::SocketAddr;
 
 mod signing;
@@ -28,21 +32,32 @@ use rand::RngCore;
 
 async fn index() -> Html<String> {
     render_html(
-        None, None, None, None, None, None, None, None, None, None, None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        GameRules::default(),
+        None,
     )
     .await
 }
 
 fn process_input_data(mut input_data: FormData) -> FormData {
     // Ensure random nonce exists
-    if input_data.random.as_ref().map_or(true, |r| r.is_empty()) {
+    if input_data.random.as_deref().unwrap_or_default().is_empty() {
         input_data.random = Some(nanoid!(12));
     }
 
     // Generate a 32-byte token in each button press
     input_data.turn_token = Some(generate_raw_token_base64());
 
-    return input_data;
+    input_data
 }
 
 async fn generate_keys() -> Json<serde_json::Value> {
@@ -71,17 +86,30 @@ async fn submit(Form(input_data): Form<FormData>) -> Html<String> {
     let random = data.random.clone();
     let board = data.board.clone();
     let shots = data.shots.clone();
+    let form_rules = unmarshal_rules(&data).ok().flatten();
 
     let response_text = match data.button.as_str() {
         "Join" => join_game(data).await,
+        "Start" => start(data).await,
         "Fire" => fire(data).await,
         "Report" => report(data).await,
         "Wave" => wave(data).await,
         "Win" => win(data).await,
         "Contest" => contest(data).await,
+        "Defeat" => defeat(data).await,
+        "History" => history(data).await,
+        "Reveal" => reveal(data).await,
         _ => "Unknown button pressed".to_string(),
     };
 
+    // Draw the board of the game being played, or the one being set up
+    let rules = match gameid.as_deref() {
+        Some(id) if !id.is_empty() => fetch_rules(id).await.ok(),
+        _ => None,
+    }
+    .or(form_rules)
+    .unwrap_or_default();
+
     render_html(
         d_pubkey,
         d_privkey,
@@ -93,11 +121,13 @@ async fn submit(Form(input_data): Form<FormData>) -> Html<String> {
         random,
         board,
         shots,
+        rules,
         Some(response_text),
     )
     .await
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn render_html(
     d_pubkey: Option<String>,
     d_privkey: Option<String>,
@@ -109,6 +139,7 @@ async fn render_html(
     random: Option<String>,
     board: Option<String>,
     shots: Option<String>,
+    rules: GameRules,
     response: Option<String>,
 ) -> Html<String> {
     let d_pubkey = d_pubkey.unwrap_or("".to_string());
@@ -120,7 +151,7 @@ async fn render_html(
     let gameid = gameid.unwrap_or("".to_string());
     let response_html = if let Some(response) = response {
         if response == "OK" {
-            if gameid != "" {
+            if !gameid.is_empty() {
                 format!(
                     "Playing Game: <b>{}</b> with fleet's ID: <b>{}</b> ",
                     gameid, fleetid
@@ -154,6 +185,8 @@ async fn render_html(
     let html = html.replace("{random}", &random);
     let html = html.replace("{board}", &board);
     let html = html.replace("{shots}", &shots);
+    let html = html.replace("{width}", &rules.width.to_string());
+    let html = html.replace("{height}", &rules.height.to_string());
     // let html = html.replace("{d_pubkey}", &d_pubkey);
     // let html = html.replace("{d_privkey}", &d_privkey);
     // let html = html.replace("{rsa_pubkey}", &rsa_pubkey);
@@ -4,8 +4,20 @@ use base64::{engine::general_purpose, Engine as _};
 use fleetcore::SignedMessage;
 use pqcrypto_dilithium::dilithium2::{detached_sign, keypair, PublicKey, SecretKey};
 use pqcrypto_traits::sign::{DetachedSignature as _, PublicKey as _, SecretKey as _};
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Last nonce signed with each Dilithium public key.
+static LAST_NONCES: Mutex<BTreeMap<Vec<u8>, u64>> = Mutex::new(BTreeMap::new());
 
 /// Signs a serializable payload and returns a `SignedMessage`.
+///
+/// The nonce is the current time in milliseconds, bumped past the last nonce
+/// used with the same key, so it is strictly increasing for that key as the
+/// chain requires, even for requests signed within the same millisecond.
 pub fn sign_payload<T: serde::Serialize>(
     payload: T,
     pubkey_b64: &str,
@@ -14,11 +26,23 @@ pub fn sign_payload<T: serde::Serialize>(
     let pk = import_key_base64(pubkey_b64);
     let sk = import_key_base64(privkey_b64);
 
-    let payload_bytes = serde_json::to_vec(&payload).ok()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_millis() as u64;
+    let nonce = {
+        let mut last_nonces = LAST_NONCES.lock().unwrap();
+        let last = last_nonces.entry(pk.clone()).or_default();
+        *last = now.max(*last + 1);
+        *last
+    };
+
+    let payload_bytes = serde_json::to_vec(&(nonce, &payload)).ok()?;
     let signature = sign_message(&payload_bytes, &sk);
 
     Some(SignedMessage {
         payload,
+        nonce,
         signature,
         public_key: pk,
     })
@@ -21,7 +21,7 @@ pub fn prepare_turn_token(rsa_pubkey_base64: &str, token_b64: &str) -> Option<(S
     // Compute hash of token (commitment)
     let token = import_key_base64(token_b64);
     let token_hash = Sha256::digest(&token);
-    let token_hash_array = <[u8; 32]>::try_from(token_hash).ok()?;
+    let token_hash_array: [u8; 32] = token_hash.into();
 
     // Encrypt token to recipient
     let enc = rsa_pub.encrypt(&mut OsRng, Pkcs1v15Encrypt, &token).ok()?;
@@ -34,8 +34,7 @@ pub fn prepare_turn_token(rsa_pubkey_base64: &str, token_b64: &str) -> Option<(S
 pub fn generate_raw_token_base64() -> String {
     let mut token = [0u8; 32];
     OsRng.fill_bytes(&mut token);
-    let token_b64 = general_purpose::STANDARD.encode(token);
-    token_b64
+    general_purpose::STANDARD.encode(token)
 }
 
 /// Generates an RSA keypair (2048-bit)
@@ -1,26 +1,28 @@
-// methods/guest/src/bin/contest.rs
-
-use fleetcore::{BaseInputs, BaseJournal};
+use fleetcore::{BaseInputs, BaseJournal, COMMITMENT_VERSION};
 use proofs::hash_board;
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::Digest;
 
 fn main() {
+    // Read the input
     let input: BaseInputs = env::read();
 
     // Check that the fleet still has ships (i.e., contest is valid)
     assert!(
-        !input.board.is_empty(),
+        !input.board.is_sunk(),
         "You cannot contest with an empty fleet"
     );
 
-    // Compute commitment: H(nonce || board)
+    // Compute the board commitment
     let digest = hash_board(&input.board, &input.random);
 
     // Commit public output
     let output = BaseJournal {
         gameid: input.gameid,
         fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         board: digest,
         token_commitment: Digest::default(), // null
     };
@@ -1,4 +1,6 @@
-use fleetcore::{FireInputs, FireJournal};
+use fleetcore::{
+    commit_shot_log, extend_shot_log, FireInputs, FireJournal, Shot, COMMITMENT_VERSION,
+};
 use proofs::hash_board;
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::Digest;
@@ -10,10 +12,19 @@ fn main() {
 
     // Validate that the fleet is NOT fully sunk
     assert!(
-        !input.board.is_empty(),
+        !input.board.is_sunk(),
         "Your fleet is fully sunk. Cannot fire!"
     );
 
+    // Validate that the fleet does not fire at itself
+    assert!(input.target != input.fleet, "A fleet cannot fire at itself");
+
+    // Validate that the shot lands on the board
+    assert!(
+        input.rules.contains(input.pos),
+        "Position is outside the board"
+    );
+
     // Validate token ownership
     let token_hash: Option<Digest> = input.token_auth.as_ref().map(|auth| {
         let hash = Sha256::digest(&auth.token);
@@ -25,17 +36,36 @@ fn main() {
         digest
     });
 
-    // Commitment hash: Hash(nonce || board)
+    // Validate that the shot is new, and extend the committed shot log with it
+    let shot = Shot {
+        target: input.target.clone(),
+        pos: input.pos,
+    };
+    assert!(
+        !input.shot_log.contains(&shot),
+        "You already fired at this position of {}",
+        input.target
+    );
+    let shot_log = commit_shot_log(&input.shot_log);
+    let next_shot_log = extend_shot_log(&shot_log, &shot);
+
+    // Compute the board commitment
     let board_hash = hash_board(&input.board, &input.random);
 
     // Build fire journal
     let output = FireJournal {
         gameid: input.gameid,
         fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         target: input.target,
         pos: input.pos,
         board: board_hash,
         token_commitment: token_hash.expect("Token hash missing"),
+        shot_log,
+        next_shot_log,
+        shots_taken: input.shot_log.len() as u32 + 1,
     };
 
     // Write public output to the journal
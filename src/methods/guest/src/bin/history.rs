@@ -0,0 +1,90 @@
+//! zkVM guest program folding a fleet's reports into one history receipt
+//!
+//! Each run verifies, as assumptions, either the fleet's join receipt or the
+//! previous history receipt together with the next report receipt. The result
+//! proves that the fleet's current board commitment descends from the one it
+//! joined with through every folded report.
+
+use fleetcore::{
+    BaseJournal, HistoryImageIds, HistoryInputs, HistoryJournal, HistoryStep, ReportJournal,
+    SalvoReportJournal,
+};
+use risc0_zkvm::guest::env;
+use risc0_zkvm::serde::from_slice;
+use risc0_zkvm::sha::Digest;
+
+fn main() {
+    let input: HistoryInputs = env::read();
+    let ids = input.image_ids;
+
+    let output = match input.step {
+        HistoryStep::Join { journal } => {
+            verify(ids.join, &journal);
+            let join: BaseJournal = from_slice(&journal).expect("Malformed journal");
+            HistoryJournal {
+                gameid: join.gameid,
+                fleet: join.fleet,
+                signer: input.signer,
+                commitment_version: join.commitment_version,
+                rules: join.rules,
+                image_ids: ids,
+                joined: join.board,
+                board: join.board,
+                reports: 0,
+            }
+        }
+        HistoryStep::Report { previous, journal } => {
+            let previous = previous_history(&ids, &previous);
+            verify(ids.report, &journal);
+            let report: ReportJournal = from_slice(&journal).expect("Malformed journal");
+            assert!(
+                report.gameid == previous.gameid
+                    && report.fleet == previous.fleet
+                    && report.commitment_version == previous.commitment_version
+                    && report.rules == previous.rules,
+                "Report belongs to another fleet or game"
+            );
+            assert_eq!(report.board, previous.board, "Report skips a board state");
+            extend(previous, input.signer, report.next_board)
+        }
+        HistoryStep::SalvoReport { previous, journal } => {
+            let previous = previous_history(&ids, &previous);
+            verify(ids.salvo_report, &journal);
+            let report: SalvoReportJournal = from_slice(&journal).expect("Malformed journal");
+            assert!(
+                report.gameid == previous.gameid
+                    && report.fleet == previous.fleet
+                    && report.commitment_version == previous.commitment_version
+                    && report.rules == previous.rules,
+                "Report belongs to another fleet or game"
+            );
+            assert_eq!(report.board, previous.board, "Report skips a board state");
+            extend(previous, input.signer, report.next_board)
+        }
+    };
+
+    env::commit(&output);
+}
+
+/// Verifies that `journal` comes from a receipt of `image_id`.
+fn verify(image_id: Digest, journal: &[u8]) {
+    env::verify(image_id, journal).expect("Receipt verification failed");
+}
+
+/// Verifies the history receipt being extended, which must trust the same images.
+fn previous_history(ids: &HistoryImageIds, journal: &[u8]) -> HistoryJournal {
+    verify(ids.history, journal);
+    let previous: HistoryJournal = from_slice(journal).expect("Malformed journal");
+    assert_eq!(&previous.image_ids, ids, "History relies on other images");
+    previous
+}
+
+/// The history one report further on.
+fn extend(previous: HistoryJournal, signer: Digest, board: Digest) -> HistoryJournal {
+    HistoryJournal {
+        signer,
+        board,
+        reports: previous.reports + 1,
+        ..previous
+    }
+}
@@ -1,4 +1,4 @@
-use fleetcore::{BaseInputs, BaseJournal};
+use fleetcore::{BaseInputs, BaseJournal, COMMITMENT_VERSION};
 use proofs::hash_board;
 use proofs::validate::validate_battleship_board;
 use risc0_zkvm::guest::env;
@@ -9,17 +9,20 @@ fn main() {
     let input: BaseInputs = env::read();
 
     // Validate fleet configuration
-    if !validate_battleship_board(&input.board) {
+    if !validate_battleship_board(&input.board, &input.rules) {
         panic!("Invalid fleet configuration");
     }
 
-    // Compute commitment: H(nonce || board)
+    // Compute the board commitment
     let board_hash = hash_board(&input.board, &input.random);
 
     // Commit public output
     let output = BaseJournal {
         gameid: input.gameid,
         fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         board: board_hash,
         token_commitment: Digest::default(), // null
     };
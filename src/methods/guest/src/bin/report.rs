@@ -2,8 +2,10 @@
 //!
 //! This proof verifies whether a player truthfully responded to an opponent’s shot,
 //! and commits both the previous and updated board hashes in the output journal.
+//! A shot that sinks a ship must be reported as such, disclosing the class of the
+//! ship but none of the other cells.
 
-use fleetcore::{FireInputs, ReportJournal};
+use fleetcore::{ship_class, FireInputs, ReportJournal, COMMITMENT_VERSION};
 use proofs::hash_board;
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::Digest;
@@ -11,14 +13,20 @@ use sha2::{Digest as ShaDigest, Sha256};
 
 /// Entry point for the zkVM guest program.
 ///
-/// Reads the input, determines whether the player reported a "Hit" or "Miss",
+/// Reads the input, determines whether the player reported a "Hit", "Sunk" or "Miss",
 /// and commits a `ReportJournal` with the verified board transition.
 fn main() {
     let input: FireInputs = env::read();
 
+    // Validate that the reported shot lands on the board
+    assert!(
+        input.rules.contains(input.pos),
+        "Position is outside the board"
+    );
+
     // Validate and compute based on report type
     let journal = match input.target.as_str() {
-        "Hit" => handle_hit(&input),
+        "Hit" | "Sunk" => handle_hit(&input),
         "Miss" => handle_miss(&input),
         _ => panic!("Invalid report value: {}", input.target),
     };
@@ -26,15 +34,8 @@ fn main() {
     env::commit(&journal);
 }
 
-/// Handles a reported "Miss" outcome.
-///
-/// Verifies that the shot position does not exist in the board (i.e., no hit occurred).
-/// Since the board is unchanged, the same hash is committed for both original and updated states.
-///
-/// # Panics
-/// - If the position is found in the board (which contradicts a "Miss" report)
-fn handle_miss(input: &FireInputs) -> ReportJournal {
-    // Validate token ownership
+/// Checks that the reporter holds the turn token and returns its hash.
+fn token_commitment(input: &FireInputs) -> Digest {
     let token_hash: Option<Digest> = input.token_auth.as_ref().map(|auth| {
         let hash = Sha256::digest(&auth.token);
         let digest = Digest::try_from(hash.as_slice()).expect("Invalid hash size");
@@ -44,10 +45,22 @@ fn handle_miss(input: &FireInputs) -> ReportJournal {
         );
         digest
     });
+    token_hash.expect("Token hash missing")
+}
+
+/// Handles a reported "Miss" outcome.
+///
+/// Verifies that the shot position does not exist in the board (i.e., no hit occurred).
+/// Since the board is unchanged, the same hash is committed for both original and updated states.
+///
+/// # Panics
+/// - If the position is found in the board (which contradicts a "Miss" report)
+fn handle_miss(input: &FireInputs) -> ReportJournal {
+    let token_commitment = token_commitment(input);
 
     // Position must not be present in the board
     assert!(
-        !input.board.contains(&input.pos),
+        input.board.ship_at(input.pos).is_none(),
         "Claimed miss, but target position was a hit"
     );
 
@@ -58,59 +71,72 @@ fn handle_miss(input: &FireInputs) -> ReportJournal {
     ReportJournal {
         gameid: input.gameid.clone(),
         fleet: input.fleet.clone(),
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         report: input.target.clone(),
         pos: input.pos,
-        board: board_hash.clone(),
+        board: board_hash,
         next_board: board_hash,
-        token_commitment: token_hash.expect("Token hash missing"),
+        token_commitment,
     }
 }
 
-/// Handles a reported "Hit" outcome.
+/// Handles a reported "Hit" or "Sunk" outcome.
 ///
-/// Verifies that the position was removed from the board, then reconstructs the
-/// original board state and computes both the old and new commitment hashes.
+/// Verifies that the position belongs to a ship of the committed board, removes
+/// it, and computes both the old and new commitment hashes. If that was the last
+/// cell of the ship, the report must be "Sunk" and is committed as
+/// `Sunk(<ship class>)`.
 ///
 /// # Panics
-/// - If the position is still present in the updated board (shot not applied)
+/// - If the position is not part of any ship (which contradicts a hit)
+/// - If the report does not match whether the ship went down
 fn handle_hit(input: &FireInputs) -> ReportJournal {
-    // Validate token ownership
-    let token_hash: Option<Digest> = input.token_auth.as_ref().map(|auth| {
-        let hash = Sha256::digest(&auth.token);
-        let digest = Digest::try_from(hash.as_slice()).expect("Invalid hash size");
-        assert_eq!(
-            &digest, &auth.expected_hash,
-            "Token mismatch: you do not own the turn"
-        );
-        digest
-    });
+    let token_commitment = token_commitment(input);
 
-    // Position must no longer be present in the board
-    assert!(
-        !input.board.contains(&input.pos),
-        "Claimed hit, but position still in updated board",
-    );
+    // Position must be present in the board
+    let ship = input
+        .board
+        .ship_at(input.pos)
+        .expect("Claimed hit, but target position was a miss");
+
+    // Apply the shot
+    let mut next_board = input.board.clone();
+    next_board.ships[ship].retain(|&pos| pos != input.pos);
 
-    // Reconstruct original board
-    let mut original_board = input.board.clone();
-    original_board.push(input.pos);
-    original_board.sort();
+    let report = if next_board.ships[ship].is_empty() {
+        assert_eq!(
+            input.target, "Sunk",
+            "This shot sank a ship; report it as Sunk"
+        );
+        format!("Sunk({})", ship_class(input.rules.ships[ship]))
+    } else {
+        assert_eq!(
+            input.target, "Hit",
+            "Claimed sunk, but the ship is still afloat"
+        );
+        input.target.clone()
+    };
 
     // Hash original board
     // This is the committed board before the shot
-    let board_hash = hash_board(&original_board, &input.random);
+    let board_hash = hash_board(&input.board, &input.random);
 
     // Hash next board
     // This is the updated board after the shot
-    let next_board_hash = hash_board(&input.board, &input.random);
+    let next_board_hash = hash_board(&next_board, &input.random);
 
     ReportJournal {
         gameid: input.gameid.clone(),
         fleet: input.fleet.clone(),
-        report: input.target.clone(),
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
+        report,
         pos: input.pos,
         board: board_hash,
         next_board: next_board_hash,
-        token_commitment: token_hash.expect("Token hash missing"),
+        token_commitment,
     }
 }
@@ -0,0 +1,32 @@
+//! zkVM guest program publishing a fleet's board once the game is over
+//!
+//! The opening is public, so this proof only checks it is a legal fleet for the
+//! game's rules and binds it to the signer; the chain audits it against the
+//! fleet's commitments itself.
+
+use fleetcore::{BaseInputs, RevealJournal, COMMITMENT_VERSION};
+use proofs::validate::validate_battleship_board;
+use risc0_zkvm::guest::env;
+
+fn main() {
+    // Read the input
+    let input: BaseInputs = env::read();
+
+    // Only a board that could have joined the game can be opened
+    if !validate_battleship_board(&input.board, &input.rules) {
+        panic!("Invalid fleet configuration");
+    }
+
+    // Commit the opening as public output
+    let output = RevealJournal {
+        gameid: input.gameid,
+        fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
+        board: input.board,
+        random: input.random,
+    };
+
+    env::commit(&output);
+}
@@ -0,0 +1,87 @@
+use fleetcore::{
+    commit_shot_log, extend_shot_log, SalvoInputs, SalvoJournal, Shot, COMMITMENT_VERSION,
+};
+use proofs::hash_board;
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::Digest;
+use sha2::{Digest as ShaDigest, Sha256};
+
+fn main() {
+    // Read the input
+    let input: SalvoInputs = env::read();
+
+    // Validate that the fleet is NOT fully sunk
+    assert!(
+        !input.board.is_sunk(),
+        "Your fleet is fully sunk. Cannot fire!"
+    );
+
+    // Validate that the fleet does not fire at itself
+    assert!(input.target != input.fleet, "A fleet cannot fire at itself");
+
+    // Validate the salvo: one shot per surviving ship at most, each on the board and distinct
+    assert!(!input.shots.is_empty(), "A salvo needs at least one shot");
+    assert!(
+        input.shots.len() <= input.board.afloat(),
+        "A salvo has at most one shot per ship afloat ({})",
+        input.board.afloat()
+    );
+    for (i, pos) in input.shots.iter().enumerate() {
+        assert!(input.rules.contains(*pos), "Position is outside the board");
+        assert!(
+            !input.shots[..i].contains(pos),
+            "The salvo fires twice at the same position"
+        );
+    }
+
+    // Validate token ownership
+    let token_hash: Option<Digest> = input.token_auth.as_ref().map(|auth| {
+        let hash = Sha256::digest(&auth.token);
+        let digest = Digest::try_from(hash.as_slice()).expect("Invalid hash size");
+        assert_eq!(
+            &digest, &auth.expected_hash,
+            "Token mismatch: you do not own the turn"
+        );
+        digest
+    });
+
+    // Validate that every shot is new, and extend the committed shot log with them
+    let shot_log = commit_shot_log(&input.shot_log);
+    let mut next_shot_log = shot_log;
+    for pos in &input.shots {
+        let shot = Shot {
+            target: input.target.clone(),
+            pos: *pos,
+        };
+        assert!(
+            !input.shot_log.contains(&shot),
+            "You already fired at this position of {}",
+            input.target
+        );
+        next_shot_log = extend_shot_log(&next_shot_log, &shot);
+    }
+
+    let shots_taken = (input.shot_log.len() + input.shots.len()) as u32;
+
+    // Compute the board commitment
+    let board_hash = hash_board(&input.board, &input.random);
+
+    // Build salvo journal
+    let output = SalvoJournal {
+        gameid: input.gameid,
+        fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
+        board: board_hash,
+        target: input.target,
+        shots: input.shots,
+        token_commitment: token_hash.expect("Token hash missing"),
+        shot_log,
+        next_shot_log,
+        shots_taken,
+    };
+
+    // Write public output to the journal
+    env::commit(&output);
+}
@@ -0,0 +1,65 @@
+//! zkVM guest program for verifying the report of a whole salvo
+//!
+//! This proof applies every shot of a salvo to the committed board at once and
+//! commits the outcome of each one, together with the previous and updated
+//! board hashes. Outcomes are computed here rather than claimed, so a sunk ship
+//! is always disclosed.
+
+use fleetcore::{ship_class, SalvoInputs, SalvoReportJournal, COMMITMENT_VERSION};
+use proofs::hash_board;
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::Digest;
+use sha2::{Digest as ShaDigest, Sha256};
+
+fn main() {
+    let input: SalvoInputs = env::read();
+
+    // Validate token ownership
+    let token_hash: Option<Digest> = input.token_auth.as_ref().map(|auth| {
+        let hash = Sha256::digest(&auth.token);
+        let digest = Digest::try_from(hash.as_slice()).expect("Invalid hash size");
+        assert_eq!(
+            &digest, &auth.expected_hash,
+            "Token mismatch: you do not own the turn"
+        );
+        digest
+    });
+
+    // Apply the shots in order: "Hit", "Sunk(<ship class>)" or "Miss" for each
+    let mut next_board = input.board.clone();
+    let mut reports = Vec::with_capacity(input.shots.len());
+    for pos in &input.shots {
+        assert!(input.rules.contains(*pos), "Position is outside the board");
+        let report = match next_board.ship_at(*pos) {
+            Some(ship) => {
+                next_board.ships[ship].retain(|p| p != pos);
+                if next_board.ships[ship].is_empty() {
+                    format!("Sunk({})", ship_class(input.rules.ships[ship]))
+                } else {
+                    "Hit".to_string()
+                }
+            }
+            None => "Miss".to_string(),
+        };
+        reports.push(report);
+    }
+
+    // Hash the board before and after the salvo
+    let board_hash = hash_board(&input.board, &input.random);
+    let next_board_hash = hash_board(&next_board, &input.random);
+
+    let journal = SalvoReportJournal {
+        gameid: input.gameid,
+        fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
+        reports,
+        shots: input.shots,
+        board: board_hash,
+        next_board: next_board_hash,
+        token_commitment: token_hash.expect("Token hash missing"),
+    };
+
+    env::commit(&journal);
+}
@@ -0,0 +1,25 @@
+use fleetcore::{BaseInputs, BaseJournal, COMMITMENT_VERSION};
+use proofs::hash_board;
+use risc0_zkvm::guest::env;
+use risc0_zkvm::sha::Digest;
+
+fn main() {
+    // Read the input
+    let input: BaseInputs = env::read();
+
+    // Compute the board commitment
+    let digest = hash_board(&input.board, &input.random);
+
+    // Commit public output
+    let output = BaseJournal {
+        gameid: input.gameid,
+        fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
+        board: digest,
+        token_commitment: Digest::default(), // null
+    };
+
+    env::commit(&output);
+}
@@ -1,4 +1,4 @@
-use fleetcore::{BaseInputs, BaseJournal};
+use fleetcore::{BaseInputs, BaseJournal, COMMITMENT_VERSION};
 use proofs::hash_board;
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::Digest;
@@ -18,13 +18,16 @@ fn main() {
         digest
     });
 
-    // Compute commitment: H(nonce || board)
+    // Compute the board commitment
     let board_hash = hash_board(&input.board, &input.random);
 
     // Commit public output
     let output = BaseJournal {
         gameid: input.gameid,
         fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         board: board_hash,
         token_commitment: token_hash.expect("Token hash missing"),
     };
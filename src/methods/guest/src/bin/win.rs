@@ -1,24 +1,49 @@
-use fleetcore::{BaseInputs, BaseJournal};
+use fleetcore::{FleetStatus, WinInputs, WinJournal, COMMITMENT_VERSION};
 use proofs::hash_board;
 use risc0_zkvm::guest::env;
 use risc0_zkvm::sha::Digest;
 
 fn main() {
     // Read the input
-    let input: BaseInputs = env::read();
+    let input: WinInputs = env::read();
 
     // Validate that the fleet is NOT fully sunk
-    assert!(!input.board.is_empty(), "Your fleet is fully sunk...");
+    assert!(!input.board.is_sunk(), "Your fleet is fully sunk...");
 
-    // Compute commitment: H(nonce || board)
+    // Validate the standings: the claimant is the only fleet left afloat
+    let mut claimant_listed = false;
+    for standing in &input.standings {
+        if standing.fleet == input.fleet {
+            assert_eq!(
+                standing.status,
+                FleetStatus::Afloat,
+                "You are out of the game"
+            );
+            claimant_listed = true;
+        } else {
+            assert_ne!(
+                standing.status,
+                FleetStatus::Afloat,
+                "Fleet {} is still afloat",
+                standing.fleet
+            );
+        }
+    }
+    assert!(claimant_listed, "You are not in the standings");
+
+    // Compute the board commitment
     let digest = hash_board(&input.board, &input.random);
 
     // Commit public output
-    let output = BaseJournal {
+    let output = WinJournal {
         gameid: input.gameid,
         fleet: input.fleet,
+        signer: input.signer,
+        commitment_version: COMMITMENT_VERSION,
+        rules: input.rules.digest(),
         board: digest,
         token_commitment: Digest::default(), // null
+        standings: input.standings,
     };
 
     env::commit(&output);
@@ -1,3 +1,5 @@
+use fleetcore::{Adjacency, Fleet, GameRules, Position};
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum Cell {
     Empty,
@@ -5,46 +7,96 @@ enum Cell {
     Visited,
 }
 
-type Board = [[Cell; 10]; 10];
+/// Row-major grid of `width * height` cells.
+struct Board {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Board {
+    fn get(&self, x: usize, y: usize) -> Cell {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x]
+        } else {
+            Cell::Empty
+        }
+    }
 
-#[allow(dead_code)]
-enum ShipType {
-    Carrier,    // size 5
-    Battleship, // size 4
-    Destroyer,  // size 3
-    Cruiser,    // size 2 (x2)
-    Submarine,  // size 1 (x2)
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.cells[y * self.width + x] = cell;
+    }
 }
 
-impl ShipType {
-    fn fleet() -> Vec<usize> {
-        vec![5, 4, 3, 2, 2, 1, 1]
+pub fn validate_battleship_board(board: &Fleet, rules: &GameRules) -> bool {
+    if rules.check().is_err() || board.ships.len() != rules.ships.len() {
+        return false;
+    }
+
+    // Every ship is a straight line of the length its place in the fleet calls for
+    for (ship, &length) in board.ships.iter().zip(&rules.ships) {
+        if ship.len() != length as usize || !is_straight(ship, rules) {
+            return false;
+        }
     }
+
+    let positions: Vec<Position> = board.cells().collect();
+    validate_cells(&positions, rules)
 }
 
-pub fn validate_battleship_board(positions: &[u8]) -> bool {
-    if positions.len() != 18 {
+/// Whether the cells form one unbroken horizontal or vertical line.
+fn is_straight(ship: &[Position], rules: &GameRules) -> bool {
+    let mut cells = ship.to_vec();
+    cells.sort_unstable();
+    let coords: Vec<(u16, u16)> = cells.iter().map(|&p| rules.coordinates(p)).collect();
+
+    coords.windows(2).all(|w| w[1] == (w[0].0 + 1, w[0].1))
+        || coords.windows(2).all(|w| w[1] == (w[0].0, w[0].1 + 1))
+}
+
+/// Checks the cells of the whole fleet: on the board, without overlaps, apart
+/// from each other as the adjacency rule requires, and making up the fleet.
+fn validate_cells(positions: &[Position], rules: &GameRules) -> bool {
+    if positions.len() != rules.fleet_cells() as usize {
         return false;
     }
 
-    let mut board = [[Cell::Empty; 10]; 10];
+    let mut board = Board {
+        width: rules.width as usize,
+        height: rules.height as usize,
+        cells: vec![Cell::Empty; rules.area() as usize],
+    };
     for &pos in positions {
-        if pos >= 100 {
+        if !rules.contains(pos) {
             return false;
         }
-        let x = (pos % 10) as usize;
-        let y = (pos / 10) as usize;
-        if board[y][x] != Cell::Empty {
+        let x = (pos % rules.width) as usize;
+        let y = (pos / rules.width) as usize;
+        if board.get(x, y) != Cell::Empty {
             return false; // duplicate
         }
-        board[y][x] = Cell::Ship;
+        board.set(x, y, Cell::Ship);
+    }
+
+    // Separated ships may not even touch at the corners
+    if rules.adjacency == Adjacency::Separated {
+        for y in 0..board.height {
+            for x in 0..board.width {
+                if board.get(x, y) == Cell::Ship
+                    && (board.get(x + 1, y + 1) == Cell::Ship
+                        || (x > 0 && board.get(x - 1, y + 1) == Cell::Ship))
+                {
+                    return false;
+                }
+            }
+        }
     }
 
     let mut sizes_found = Vec::new();
 
-    for y in 0..10 {
-        for x in 0..10 {
-            if board[y][x] == Cell::Ship {
+    for y in 0..board.height {
+        for x in 0..board.width {
+            if board.get(x, y) == Cell::Ship {
                 let size = explore_ship(&mut board, x, y);
                 if size == 0 {
                     return false;
@@ -55,7 +107,7 @@ pub fn validate_battleship_board(positions: &[u8]) -> bool {
     }
 
     sizes_found.sort_unstable();
-    let mut expected = ShipType::fleet();
+    let mut expected: Vec<usize> = rules.ships.iter().map(|&s| s as usize).collect();
     expected.sort_unstable();
 
     sizes_found == expected
@@ -64,15 +116,8 @@ pub fn validate_battleship_board(positions: &[u8]) -> bool {
 fn explore_ship(board: &mut Board, x: usize, y: usize) -> usize {
     let mut length = 0;
 
-    let mut is_horizontal = false;
-    let mut is_vertical = false;
-
-    if x + 1 < 10 && board[y][x + 1] == Cell::Ship {
-        is_horizontal = true;
-    }
-    if y + 1 < 10 && board[y + 1][x] == Cell::Ship {
-        is_vertical = true;
-    }
+    let is_horizontal = board.get(x + 1, y) == Cell::Ship;
+    let is_vertical = board.get(x, y + 1) == Cell::Ship;
 
     if is_horizontal && is_vertical {
         return 0; // invalid L-shape
@@ -80,15 +125,15 @@ fn explore_ship(board: &mut Board, x: usize, y: usize) -> usize {
 
     if is_horizontal {
         let mut j = x;
-        while j < 10 && board[y][j] == Cell::Ship {
-            board[y][j] = Cell::Visited;
+        while board.get(j, y) == Cell::Ship {
+            board.set(j, y, Cell::Visited);
             length += 1;
 
             // Check for vertical adjacency
-            if y > 0 && board[y - 1][j] == Cell::Ship {
+            if y > 0 && board.get(j, y - 1) == Cell::Ship {
                 return 0;
             }
-            if y + 1 < 10 && board[y + 1][j] == Cell::Ship {
+            if board.get(j, y + 1) == Cell::Ship {
                 return 0;
             }
 
@@ -96,22 +141,22 @@ fn explore_ship(board: &mut Board, x: usize, y: usize) -> usize {
         }
     } else if is_vertical {
         let mut i = y;
-        while i < 10 && board[i][x] == Cell::Ship {
-            board[i][x] = Cell::Visited;
+        while board.get(x, i) == Cell::Ship {
+            board.set(x, i, Cell::Visited);
             length += 1;
 
             // Check for horizontal adjacency
-            if x > 0 && board[i][x - 1] == Cell::Ship {
+            if x > 0 && board.get(x - 1, i) == Cell::Ship {
                 return 0;
             }
-            if x + 1 < 10 && board[i][x + 1] == Cell::Ship {
+            if board.get(x + 1, i) == Cell::Ship {
                 return 0;
             }
 
             i += 1;
         }
     } else {
-        board[y][x] = Cell::Visited;
+        board.set(x, y, Cell::Visited);
         length = 1;
 
         // Check surroundings
@@ -123,7 +168,7 @@ fn explore_ship(board: &mut Board, x: usize, y: usize) -> usize {
         ];
 
         for (nx, ny) in neighbors {
-            if nx < 10 && ny < 10 && board[ny][nx] == Cell::Ship {
+            if board.get(nx, ny) == Cell::Ship {
                 return 0;
             }
         }